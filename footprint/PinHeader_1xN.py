@@ -0,0 +1,22 @@
+# the number of pins and the pitch can be tuned in the GUI
+PARAMS = {
+    "n": (4, 1, 40),
+    "pitch": (2.54, 1.0, 5.0),
+}
+
+def footprint(n, pitch):
+    l = n * pitch
+
+    name = Name("PinHeader_1x%02d_P%.2fmm" % (n, pitch))
+    name.y = l/2 + 1.5
+
+    reference = Reference()
+    reference.y = -l/2 - 1.5
+
+    fab = FFab(pitch, l)
+    crtyd = FCrtYd(pitch + 0.5, l + 0.5)
+
+    pad = Pad(1, 0.7*pitch, 0.4*pitch)
+    pads = single(pad, pitch, n)
+
+    return [name, reference, fab, crtyd] + pads
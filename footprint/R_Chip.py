@@ -0,0 +1,25 @@
+# (size, body dx, body dy, pad dx, pad dy, pad spacing)
+SIZES = [
+    ("0402", 1.0, 0.5, 0.6, 0.5, 1.1),
+    ("0603", 1.6, 0.8, 0.9, 0.95, 1.65),
+    ("0805", 2.0, 1.25, 1.0, 1.45, 1.9),
+]
+
+def footprints():
+    for (size, bx, by, px, py, s) in SIZES:
+        yield (size, chip(size, bx, by, px, py, s))
+
+def chip(size, bx, by, px, py, s):
+    name = Name("R_%s" % size)
+    name.y = by/2 + 1.0
+
+    reference = Reference()
+    reference.y = -by/2 - 1.0
+
+    fab = FFab(bx, by)
+    crtyd = FCrtYd(s + px + 0.5, py + 0.5)
+
+    p1 = Smd(1, (px, py), (-s/2, 0))
+    p2 = Smd(2, (px, py), (s/2, 0))
+
+    return [name, reference, fab, crtyd, p1, p2]
@@ -4,6 +4,9 @@ def footprint():
     
     reference = Reference()
     reference.y = -3.5
+
+    desc = Desc("TSSOP-14, 5x4.4mm body, 0.65mm pitch, exposed pad")
+    tags = Tags("TSSOP HTSSOP SO")
     
     fab = FFab(4.5, 5)
     fab.corner = 0.4
@@ -15,9 +18,11 @@ def footprint():
     #q = 5/0
 
     via = Pad(15, 0.63, 0.33)
+    via.allow_duplicate = True
     vias = dual(via, 0.95, 1.5, 6, 15)
     
     ep = Smd(15, (3.4, 5))
+    ep.allow_duplicate = True
     ep.layers.remove("F.Mask")
     ep.layers.remove("F.Paste")
     paste = FPaste(2.46, 2.31)
@@ -29,5 +34,5 @@ def footprint():
     l2 = Line((-3.5, -2.6), (2.25, -2.6))
     lines = [l1, l2]
     
-    return [name, reference, fab, crtyd, mask, paste] + smds + vias + lines
+    return [name, reference, desc, tags, fab, crtyd, mask, paste] + smds + vias + lines
  
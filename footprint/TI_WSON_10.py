@@ -15,9 +15,11 @@ def footprint():
     #q = 5/0
 
     via = Pad(11, 0.5, 0.2)
+    via.allow_duplicate = True
     vias = single(via, 0.95, 3, 11)
     
     ep = Smd(11, (0.84, 2.4))
+    ep.allow_duplicate = True
 
     l1 = Line((-1, 1.65), (1, 1.65))
     l2 = Line((-1.35, -1.65), (1, -1.65))
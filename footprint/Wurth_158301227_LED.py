@@ -13,11 +13,9 @@ def footprint():
 
     smd1 = Smd(1, (2.0, 0.8))
     smd1.x = -2/2 + w/2
-    smd1.x = - smd1.x
     smd2 = Smd(2, (1.2, 0.8))
     smd2.x = 1.2/2 - w/2
-    smd2.x = -smd2.x
-    smds = [smd1, smd2]
+    smds = mirror_x([smd1, smd2])
 
     l1 = Line((-3/2, 1.4/2), (3/2, 1.4/2))
     l2 = Line((-3/2-0.3, -1.4/2), (3/2, -1.4/2))
@@ -0,0 +1,151 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Common types for the results of the footprint checks.
+
+use std::fmt;
+
+use element::{Bound, BoundingBox, Line, Pad, Rect, Smd, Text};
+use geometry::{Outline, Point};
+use kicad::Footprint;
+use layers::Layer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// the footprint element an issue is about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementRef {
+    /// kind of element, e.g. "pad" or "line"
+    pub kind: String,
+    /// pad name, text or layer of the element
+    pub name: String,
+    pub bound: Bound,
+}
+
+impl ElementRef {
+    pub fn new(kind: &str, name: &str, bound: Bound) -> ElementRef {
+        ElementRef {
+            kind: kind.into(),
+            name: name.into(),
+            bound,
+        }
+    }
+
+    pub fn smd(smd: &Smd) -> ElementRef {
+        ElementRef::new("pad", &smd.name, smd.bounding_box())
+    }
+
+    pub fn pad(pad: &Pad) -> ElementRef {
+        ElementRef::new("pad", &pad.name, pad.bounding_box())
+    }
+
+    pub fn line(line: &Line) -> ElementRef {
+        ElementRef::new("line", &line.layer.to_string(), line.bounding_box())
+    }
+
+    pub fn rect(rect: &Rect) -> ElementRef {
+        ElementRef::new("rect", &rect.layer.to_string(), rect.bounding_box())
+    }
+
+    pub fn text(kind: &str, text: &Text) -> ElementRef {
+        ElementRef::new(kind, &text.txt, text.bounding_box())
+    }
+}
+
+impl fmt::Display for ElementRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.name)
+    }
+}
+
+/// one problem found by a check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    /// id of the rule, e.g. "F5.3"
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub element: Option<ElementRef>,
+}
+
+impl Issue {
+    pub fn new(
+        rule: &str,
+        severity: Severity,
+        message: String,
+        element: Option<ElementRef>,
+    ) -> Issue {
+        Issue {
+            rule: rule.into(),
+            severity,
+            message,
+            element,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {}", self.severity, self.rule, self.message)?;
+        if let Some(ref element) = self.element {
+            write!(f, " [{}]", element)?;
+        }
+        Ok(())
+    }
+}
+
+/// line segments of all lines and rects on a layer, with their width
+pub fn segments(f: &Footprint, layer: Layer) -> Vec<(Point, Point, f64, ElementRef)> {
+    let mut segments = vec![];
+    for line in f.lines.iter().filter(|l| l.layer == layer) {
+        segments.push((
+            Point::new(line.x1, line.y1),
+            Point::new(line.x2, line.y2),
+            line.w,
+            ElementRef::line(line),
+        ));
+    }
+    for rect in f.rects.iter().filter(|r| r.layer == layer) {
+        let c = rect.corners();
+        for i in 0..4 {
+            segments.push((c[i], c[(i + 1) % 4], rect.w, ElementRef::rect(rect)));
+        }
+    }
+    segments
+}
+
+/// copper and silkscreen layer of the front and of the back
+pub const SIDES: [(Layer, Layer); 2] = [(Layer::FCu, Layer::FSilkS), (Layer::BCu, Layer::BSilkS)];
+
+/// all copper pads with their outline and the copper layer of their side;
+/// None for through hole pads, they are on both sides
+pub fn pad_outlines(f: &Footprint) -> Vec<(Outline, ElementRef, Option<Layer>)> {
+    f.smd
+        .iter()
+        .map(|s| (s.outline(), ElementRef::smd(s), Some(s.copper_layer())))
+        .chain(f.pad.iter().map(|p| (p.outline(), ElementRef::pad(p), None)))
+        .collect()
+}
+
+/// the copper pads on the side of a copper layer, with their outline
+pub fn pad_outlines_on(f: &Footprint, copper: Layer) -> Vec<(Outline, ElementRef)> {
+    pad_outlines(f)
+        .into_iter()
+        .filter(|&(_, _, side)| side.map_or(true, |l| l == copper))
+        .map(|(outline, pad, _)| (outline, pad))
+        .collect()
+}
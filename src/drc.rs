@@ -0,0 +1,370 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Design rule checks: can the footprint be manufactured by a given
+// fab house. The limits come from a fab profile in the settings.
+
+use check::{pad_outlines, pad_outlines_on, segments, ElementRef, Issue, Severity, SIDES};
+use kicad::Footprint;
+use layers::Layer;
+
+const EPSILON: f64 = 1e-6;
+
+/// manufacturing limits of a fab house, all in mm
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FabProfile {
+    /// copper to copper clearance between pads
+    pub clearance: f64,
+    /// minimum annular ring of plated holes
+    pub annular_ring: f64,
+    pub min_drill: f64,
+    pub min_silk_width: f64,
+    pub min_silk_text_height: f64,
+    /// clearance between silkscreen and pads
+    pub silk_clearance: f64,
+}
+
+impl Default for FabProfile {
+    fn default() -> FabProfile {
+        FabProfile {
+            clearance: 0.15,
+            annular_ring: 0.15,
+            min_drill: 0.3,
+            min_silk_width: 0.15,
+            min_silk_text_height: 0.8,
+            silk_clearance: 0.15,
+        }
+    }
+}
+
+fn check_pads(issues: &mut Vec<Issue>, f: &Footprint, profile: &FabProfile) {
+    let sizes = f.smd
+        .iter()
+        .map(|s| (s.dx, s.dy, ElementRef::smd(s)))
+        .chain(f.pad.iter().map(|p| (p.dx, p.dy, ElementRef::pad(p))));
+    for (dx, dy, pad) in sizes {
+        if !(dx > 0.0 && dy > 0.0) {
+            issues.push(Issue::new(
+                "pad-size",
+                Severity::Error,
+                format!("pad size is {} x {} mm, it should be positive", dx, dy),
+                Some(pad),
+            ));
+        }
+    }
+    let pads = pad_outlines(f);
+    for (i, &(ref o1, ref p1, side1)) in pads.iter().enumerate() {
+        for &(ref o2, ref p2, side2) in &pads[i + 1..] {
+            // pads with the same name are connected anyway, and pads on
+            // opposite sides don't touch
+            if p1.name == p2.name || (side1.is_some() && side2.is_some() && side1 != side2) {
+                continue;
+            }
+            let d = o1.distance(o2);
+            if d < profile.clearance - EPSILON {
+                issues.push(Issue::new(
+                    "clearance",
+                    Severity::Error,
+                    format!(
+                        "pads {} and {} are {:.3} mm apart, minimum is {} mm",
+                        p1.name, p2.name, d, profile.clearance
+                    ),
+                    Some(p1.clone()),
+                ));
+            }
+        }
+    }
+    for pad in &f.pad {
+        if pad.drill < profile.min_drill - EPSILON {
+            issues.push(Issue::new(
+                "drill",
+                Severity::Error,
+                format!("drill is {} mm, minimum is {} mm", pad.drill, profile.min_drill),
+                Some(ElementRef::pad(pad)),
+            ));
+        }
+        if pad.plated {
+            let ring = (pad.dx.min(pad.dy) - pad.drill) / 2.0;
+            if ring < profile.annular_ring - EPSILON {
+                issues.push(Issue::new(
+                    "annular-ring",
+                    Severity::Error,
+                    format!(
+                        "annular ring is {:.3} mm, minimum is {} mm",
+                        ring, profile.annular_ring
+                    ),
+                    Some(ElementRef::pad(pad)),
+                ));
+            }
+        }
+    }
+}
+
+fn check_silk(issues: &mut Vec<Issue>, f: &Footprint, profile: &FabProfile) {
+    let silk = segments(f, Layer::FSilkS)
+        .into_iter()
+        .chain(segments(f, Layer::BSilkS))
+        .collect::<Vec<_>>();
+    for &(_, _, w, ref e) in &silk {
+        if w < profile.min_silk_width - EPSILON {
+            issues.push(Issue::new(
+                "silk-width",
+                Severity::Error,
+                format!(
+                    "silkscreen line width is {} mm, minimum is {} mm",
+                    w, profile.min_silk_width
+                ),
+                Some(e.clone()),
+            ));
+        }
+    }
+    let texts = f.name
+        .iter()
+        .map(|t| ("value", t))
+        .chain(f.reference.iter().map(|t| ("reference", t)))
+        .filter(|&(_, t)| t.layer == Layer::FSilkS || t.layer == Layer::BSilkS);
+    for (kind, text) in texts {
+        if text.dy < profile.min_silk_text_height - EPSILON {
+            issues.push(Issue::new(
+                "silk-text",
+                Severity::Error,
+                format!(
+                    "silkscreen text height is {} mm, minimum is {} mm",
+                    text.dy, profile.min_silk_text_height
+                ),
+                Some(ElementRef::text(kind, text)),
+            ));
+        }
+        if text.thickness < profile.min_silk_width - EPSILON {
+            issues.push(Issue::new(
+                "silk-width",
+                Severity::Error,
+                format!(
+                    "silkscreen text thickness is {} mm, minimum is {} mm",
+                    text.thickness, profile.min_silk_width
+                ),
+                Some(ElementRef::text(kind, text)),
+            ));
+        }
+    }
+    // silkscreen is only checked against the pads on its own side
+    for &(copper, silk_layer) in &SIDES {
+        let pads = pad_outlines_on(f, copper);
+        for (a, b, w, e) in segments(f, silk_layer) {
+            for &(ref outline, ref pad) in &pads {
+                let d = outline.segment_distance(a, b) - w / 2.0;
+                if d < profile.silk_clearance - EPSILON {
+                    issues.push(Issue::new(
+                        "silk-clearance",
+                        Severity::Error,
+                        format!(
+                            "silkscreen is {:.3} mm from pad {}, minimum is {} mm",
+                            d.max(0.0),
+                            pad.name,
+                            profile.silk_clearance
+                        ),
+                        Some(e.clone()),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// check a footprint against the limits of a fab profile
+pub fn check(f: &Footprint, profile: &FabProfile) -> Vec<Issue> {
+    let mut issues = vec![];
+    check_pads(&mut issues, f, profile);
+    check_silk(&mut issues, f, profile);
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use element::{Line, Pad, Smd, SmdShape, Text};
+
+    fn smd(name: &str, x: f64, dx: f64, dy: f64, copper: Layer) -> Smd {
+        Smd {
+            name: name.into(),
+            x,
+            y: 0.0,
+            dx,
+            dy,
+            layers: vec![copper],
+            shape: SmdShape::Rect,
+            rot: 0.0,
+            allow_duplicate: false,
+        }
+    }
+
+    fn pad(name: &str, x: f64, dx: f64, drill: f64) -> Pad {
+        Pad {
+            name: name.into(),
+            x,
+            y: 0.0,
+            dx,
+            dy: dx,
+            drill,
+            layers: vec![Layer::Cu, Layer::Mask],
+            plated: true,
+            rot: 0.0,
+            allow_duplicate: false,
+        }
+    }
+
+    fn silk(y: f64, w: f64, layer: Layer) -> Line {
+        Line {
+            x1: -1.0,
+            y1: y,
+            x2: 1.0,
+            y2: y,
+            w,
+            layer,
+        }
+    }
+
+    /// two 1 mm square pads, 1 mm apart
+    fn footprint() -> Footprint {
+        Footprint {
+            smd: vec![
+                smd("1", -1.0, 1.0, 1.0, Layer::FCu),
+                smd("2", 1.0, 1.0, 1.0, Layer::FCu),
+            ],
+            ..Footprint::default()
+        }
+    }
+
+    fn messages(f: &Footprint) -> Vec<(String, String)> {
+        check(f, &FabProfile::default())
+            .into_iter()
+            .map(|i| (i.rule, i.message))
+            .collect()
+    }
+
+    fn rules(f: &Footprint) -> Vec<String> {
+        messages(f).into_iter().map(|(rule, _)| rule).collect()
+    }
+
+    #[test]
+    fn clean() {
+        assert!(messages(&footprint()).is_empty());
+    }
+
+    #[test]
+    fn pad_clearance() {
+        let mut f = footprint();
+        // exactly at the clearance is fine
+        f.smd[1].x = 0.15;
+        assert!(messages(&f).is_empty());
+        f.smd[1].x = 0.149;
+        assert_eq!(
+            messages(&f),
+            vec![(
+                "clearance".into(),
+                "pads 1 and 2 are 0.149 mm apart, minimum is 0.15 mm".into()
+            )]
+        );
+        // pads with the same name are connected anyway
+        f.smd[1].name = "1".into();
+        assert!(messages(&f).is_empty());
+    }
+
+    #[test]
+    fn pad_sides() {
+        let mut f = footprint();
+        f.smd[1] = smd("2", -1.0, 1.0, 1.0, Layer::BCu);
+        assert!(messages(&f).is_empty());
+        // through hole pads are on both sides
+        f.pad.push(pad("3", 0.0, 1.0, 0.5));
+        assert_eq!(rules(&f), vec!["clearance", "clearance"]);
+    }
+
+    #[test]
+    fn zero_and_negative_size() {
+        let mut f = footprint();
+        f.smd.push(smd("3", 0.0, 0.0, 0.0, Layer::FCu));
+        // a zero size pad is not everywhere
+        assert_eq!(
+            messages(&f),
+            vec![(
+                "pad-size".into(),
+                "pad size is 0 x 0 mm, it should be positive".into()
+            )]
+        );
+        f.smd[2] = smd("3", 0.0, 0.0, 2.0, Layer::FCu);
+        assert_eq!(rules(&f), vec!["pad-size"]);
+        f.smd[2] = smd("3", 3.0, -1.0, 1.0, Layer::FCu);
+        assert_eq!(rules(&f), vec!["pad-size"]);
+        // but it still needs its clearance
+        f.smd[2].x = -1.0;
+        assert_eq!(rules(&f), vec!["pad-size", "clearance"]);
+    }
+
+    #[test]
+    fn drill_and_ring() {
+        let mut f = footprint();
+        f.pad.push(pad("3", 4.0, 1.0, 0.7));
+        assert!(messages(&f).is_empty());
+        f.pad[0].drill = 0.8;
+        assert_eq!(
+            messages(&f),
+            vec![(
+                "annular-ring".into(),
+                "annular ring is 0.100 mm, minimum is 0.15 mm".into()
+            )]
+        );
+        f.pad[0].plated = false;
+        f.pad[0].drill = 0.2;
+        assert_eq!(
+            messages(&f),
+            vec![("drill".into(), "drill is 0.2 mm, minimum is 0.3 mm".into())]
+        );
+    }
+
+    #[test]
+    fn silkscreen() {
+        let mut f = footprint();
+        // exactly at the silk clearance is fine
+        f.lines.push(silk(-0.725, 0.15, Layer::FSilkS));
+        assert!(messages(&f).is_empty());
+        f.lines[0].w = 0.1;
+        f.lines[0].y1 = 0.0;
+        f.lines[0].y2 = 0.0;
+        assert_eq!(
+            rules(&f),
+            vec!["silk-width", "silk-clearance", "silk-clearance"]
+        );
+        assert_eq!(
+            messages(&f)[1].1,
+            "silkscreen is 0.000 mm from pad 1, minimum is 0.15 mm"
+        );
+        // the back silkscreen is not checked against front pads
+        f.lines[0].layer = Layer::BSilkS;
+        assert_eq!(rules(&f), vec!["silk-width"]);
+    }
+
+    #[test]
+    fn silkscreen_text() {
+        let mut f = footprint();
+        let mut text = Text {
+            x: 0.0,
+            y: 3.0,
+            dy: 1.0,
+            txt: "REF**".into(),
+            thickness: 0.15,
+            shorten: None,
+            layer: Layer::FSilkS,
+        };
+        f.reference = Some(text.clone());
+        assert!(messages(&f).is_empty());
+        text.dy = 0.5;
+        text.thickness = 0.1;
+        f.reference = Some(text.clone());
+        assert_eq!(rules(&f), vec!["silk-text", "silk-width"]);
+        // text on other layers isn't printed
+        text.layer = Layer::FFab;
+        f.reference = Some(text);
+        assert!(messages(&f).is_empty());
+    }
+}
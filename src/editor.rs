@@ -0,0 +1,852 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// The script editor in the input tab: python highlighting, line numbers,
+// undo and redo, saving, and reloading or merging when the file is
+// changed by another editor.
+
+use cairo;
+use gdk;
+use gdk::ModifierType;
+use gtk;
+use gtk::prelude::*;
+use gtk::{ButtonsType, DialogFlags, DrawingArea, MessageDialog, MessageType, ResponseType};
+use gtk::{ScrolledWindow, Statusbar};
+use gtk::{TextBuffer, TextTag, TextTagTable, TextView, TextWindowType, Window};
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use error::MpError;
+
+/// width of the line number gutter in pixels
+const GUTTER_WIDTH: i32 = 40;
+
+const KEYWORDS: &'static [&'static str] = &[
+    "False", "None", "True", "and", "as", "assert", "break", "class", "continue", "def", "del",
+    "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+    "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Keyword,
+    Str,
+    Comment,
+    Number,
+}
+
+impl Token {
+    fn tag_name(&self) -> &'static str {
+        match *self {
+            Token::Keyword => "py-keyword",
+            Token::Str => "py-string",
+            Token::Comment => "py-comment",
+            Token::Number => "py-number",
+        }
+    }
+}
+
+const TOKENS: &'static [Token] = &[Token::Keyword, Token::Str, Token::Comment, Token::Number];
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// python tokens to highlight, as start and end character offsets
+fn tokens(text: &str) -> Vec<(usize, usize, Token)> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            tokens.push((start, i, Token::Comment));
+        } else if c == '\'' || c == '"' {
+            let triple = i + 2 < chars.len() && chars[i + 1] == c && chars[i + 2] == c;
+            i += if triple { 3 } else { 1 };
+            while i < chars.len() {
+                if chars[i] == '\\' {
+                    i += 2;
+                } else if triple {
+                    if i + 2 < chars.len() && chars[i] == c && chars[i + 1] == c
+                        && chars[i + 2] == c
+                    {
+                        i += 3;
+                        break;
+                    }
+                    i += 1;
+                } else if chars[i] == c {
+                    i += 1;
+                    break;
+                } else if chars[i] == '\n' {
+                    break;
+                } else {
+                    i += 1;
+                }
+            }
+            i = i.min(chars.len());
+            tokens.push((start, i, Token::Str));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (is_ident(chars[i]) || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push((start, i, Token::Number));
+        } else if is_ident(c) {
+            while i < chars.len() && is_ident(chars[i]) {
+                i += 1;
+            }
+            let word = chars[start..i].iter().collect::<String>();
+            if KEYWORDS.contains(&word.as_str()) {
+                tokens.push((start, i, Token::Keyword));
+            }
+        } else {
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// one change to the buffer, at a character offset
+#[derive(Debug, Clone, PartialEq)]
+enum Edit {
+    Insert(usize, String),
+    Delete(usize, String),
+    /// edits that are undone and redone together
+    Group(Vec<Edit>),
+}
+
+impl Edit {
+    fn inverse(&self) -> Edit {
+        match *self {
+            Edit::Insert(offset, ref text) => Edit::Delete(offset, text.clone()),
+            Edit::Delete(offset, ref text) => Edit::Insert(offset, text.clone()),
+            Edit::Group(ref edits) => Edit::Group(edits.iter().rev().map(Edit::inverse).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// set while undoing or loading, so those changes are not recorded
+    replaying: bool,
+    /// set while the edits go into the Group on top of the undo stack
+    grouping: bool,
+}
+
+fn single_char(s: &str) -> bool {
+    s.chars().count() == 1
+}
+
+impl History {
+    /// remember an edit; typing or deleting a word is one undo step
+    fn record(&mut self, edit: Edit) {
+        if self.replaying {
+            return;
+        }
+        self.redo.clear();
+        if self.grouping {
+            if let Some(&mut Edit::Group(ref mut edits)) = self.undo.last_mut() {
+                edits.push(edit);
+                return;
+            }
+        }
+        if let Some(last) = self.undo.last_mut() {
+            match (last, &edit) {
+                (&mut Edit::Insert(o, ref mut t), &Edit::Insert(n, ref s))
+                    if single_char(s) && !t.ends_with(char::is_whitespace)
+                        && n == o + t.chars().count() =>
+                {
+                    t.push_str(s);
+                    return;
+                }
+                (&mut Edit::Delete(ref mut o, ref mut t), &Edit::Delete(n, ref s))
+                    if single_char(s) && n + 1 == *o =>
+                {
+                    // backspace
+                    t.insert_str(0, s);
+                    *o = n;
+                    return;
+                }
+                (&mut Edit::Delete(o, ref mut t), &Edit::Delete(n, ref s))
+                    if single_char(s) && n == o =>
+                {
+                    // delete
+                    t.push_str(s);
+                    return;
+                }
+                _ => (),
+            }
+        }
+        self.undo.push(edit);
+    }
+
+    /// the edits until end_group are one undo step
+    fn begin_group(&mut self) {
+        self.undo.push(Edit::Group(vec![]));
+        self.grouping = true;
+    }
+
+    fn end_group(&mut self) {
+        self.grouping = false;
+        let empty = match self.undo.last() {
+            Some(Edit::Group(edits)) => edits.is_empty(),
+            _ => false,
+        };
+        if empty {
+            self.undo.pop();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// split in lines, keeping the line ends
+fn lines(text: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '\n' {
+            lines.push(&text[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// for every line of a, the index of the same line in b, following the
+/// longest common subsequence
+fn matching(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; a.len()];
+    // only the lines between the common start and end need the table
+    let prefix = a.iter().zip(b).take_while(|&(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|&(x, y)| x == y)
+        .count();
+    for (i, m) in matches.iter_mut().enumerate().take(prefix) {
+        *m = Some(i);
+    }
+    for k in 1..suffix + 1 {
+        matches[a.len() - k] = Some(b.len() - k);
+    }
+    let (a, b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let (n, m) = (a.len(), b.len());
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if a[i] == b[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            matches[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+fn ends_line(s: &str) -> bool {
+    s.is_empty() || s.ends_with('\n')
+}
+
+fn with_newline(s: &str) -> String {
+    if ends_line(s) {
+        s.into()
+    } else {
+        format!("{}\n", s)
+    }
+}
+
+/// three way merge of the lines changed in ours and in theirs since base;
+/// lines changed in both are kept with conflict markers around them
+fn merge(base: &str, ours: &str, theirs: &str) -> (String, bool) {
+    // the newline at the end is merged on its own, so adding a line after
+    // a last line without one is not a change of that line
+    let newline = if ends_line(ours) == ends_line(base) {
+        ends_line(theirs)
+    } else {
+        ends_line(ours)
+    };
+    let (mut result, conflict) =
+        merge_lines(&with_newline(base), &with_newline(ours), &with_newline(theirs));
+    if !newline && !result.ends_with(">>>>>>> disk\n") {
+        result.pop();
+    }
+    (result, conflict)
+}
+
+fn merge_lines(base: &str, ours: &str, theirs: &str) -> (String, bool) {
+    let (b, o, t) = (lines(base), lines(ours), lines(theirs));
+    let (mo, mt) = (matching(&b, &o), matching(&b, &t));
+    let mut result = String::new();
+    let mut conflict = false;
+    let (mut ib, mut io, mut it) = (0, 0, 0);
+    loop {
+        // the next base line that is in both versions
+        let stable = (ib..b.len()).find(|&k| mo[k].is_some() && mt[k].is_some());
+        let (eb, eo, et) = match stable {
+            Some(k) => (k, mo[k].unwrap(), mt[k].unwrap()),
+            None => (b.len(), o.len(), t.len()),
+        };
+        let (cb, co, ct) = (&b[ib..eb], &o[io..eo], &t[it..et]);
+        if co == ct || cb == ct {
+            result.extend(co.iter().cloned());
+        } else if cb == co {
+            result.extend(ct.iter().cloned());
+        } else {
+            conflict = true;
+            for &(marker, chunk) in &[("<<<<<<< madparts\n", co), ("=======\n", ct)] {
+                result.push_str(marker);
+                result.extend(chunk.iter().cloned());
+            }
+            result.push_str(">>>>>>> disk\n");
+        }
+        match stable {
+            Some(k) => {
+                result.push_str(b[k]);
+                ib = k + 1;
+                io = eo + 1;
+                it = et + 1;
+            }
+            None => break,
+        }
+    }
+    (result, conflict)
+}
+
+fn buffer_text(buffer: &TextBuffer) -> String {
+    let (start, end) = buffer.get_bounds();
+    buffer.get_text(&start, &end, true).unwrap_or_default()
+}
+
+fn highlight(buffer: &TextBuffer) {
+    let (start, end) = buffer.get_bounds();
+    for token in TOKENS {
+        buffer.remove_tag_by_name(token.tag_name(), &start, &end);
+    }
+    buffer.apply_tag_by_name("code", &start, &end);
+    for (from, to, token) in tokens(&buffer_text(buffer)) {
+        buffer.apply_tag_by_name(
+            token.tag_name(),
+            &buffer.get_iter_at_offset(from as i32),
+            &buffer.get_iter_at_offset(to as i32),
+        );
+    }
+}
+
+/// line numbers of the lines visible in the view
+fn draw_line_numbers(view: &TextView, area: &DrawingArea, cr: &cairo::Context) {
+    cr.set_source_rgb(0.93, 0.93, 0.93);
+    cr.paint();
+    cr.set_source_rgb(0.5, 0.5, 0.5);
+    cr.select_font_face(
+        "Monospace",
+        cairo::enums::FontSlant::Normal,
+        cairo::enums::FontWeight::Normal,
+    );
+    cr.set_font_size(10.0);
+    let width = f64::from(area.get_allocated_width());
+    let visible = view.get_visible_rect();
+    let (mut iter, _) = view.get_line_at_y(visible.y);
+    loop {
+        let (y, height) = view.get_line_yrange(&iter);
+        if y > visible.y + visible.height {
+            break;
+        }
+        let (_, wy) = view.buffer_to_window_coords(TextWindowType::Widget, 0, y);
+        let number = (iter.get_line() + 1).to_string();
+        let ext = cr.text_extents(&number);
+        cr.move_to(width - ext.x_advance - 5.0, f64::from(wy + height) - 4.0);
+        cr.show_text(&number);
+        if !iter.forward_line() {
+            break;
+        }
+    }
+}
+
+const UNSAVED: &'static str = "unsaved changes, showing the saved file";
+
+/// what to do with changes on disk when the buffer has unsaved changes
+const RELOAD: i32 = 1;
+const MERGE: i32 = 2;
+const KEEP: i32 = 3;
+
+#[derive(Clone)]
+pub struct Editor {
+    pub view: TextView,
+    pub buffer: TextBuffer,
+    /// the editor with its line numbers
+    pub widget: gtk::Box,
+    statusbar: Statusbar,
+    /// the last status, shown with a note while there are unsaved changes
+    status: Rc<RefCell<String>>,
+    path: PathBuf,
+    /// the file as it was last loaded or saved, for merging
+    base: Rc<RefCell<String>>,
+    history: Rc<RefCell<History>>,
+}
+
+impl Editor {
+    /// an editor for the script at path; the highlight tags are added to
+    /// the tags of the buffer
+    pub fn new(path: &Path, tags: &TextTagTable, statusbar: &Statusbar) -> Editor {
+        let code = TextTag::new(Some("code"));
+        code.set_property_family(Some("Monospace"));
+        tags.add(&code);
+        for &(token, color) in &[
+            (Token::Keyword, "#7f0055"),
+            (Token::Str, "#2a00ff"),
+            (Token::Comment, "#3f7f5f"),
+            (Token::Number, "#a05000"),
+        ] {
+            let tag = TextTag::new(Some(token.tag_name()));
+            tag.set_property_foreground(Some(color));
+            tags.add(&tag);
+        }
+
+        let buffer = TextBuffer::new(Some(tags));
+        let view = TextView::new_with_buffer(&buffer);
+        let scrolled = ScrolledWindow::new(None, None);
+        scrolled.add(&view);
+        let gutter = DrawingArea::new();
+        gutter.set_size_request(GUTTER_WIDTH, -1);
+        let widget = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        widget.pack_start(&gutter, false, false, 0);
+        widget.pack_start(&scrolled, true, true, 0);
+
+        let editor = Editor {
+            view,
+            buffer,
+            widget,
+            statusbar: statusbar.clone(),
+            status: Rc::new(RefCell::new(String::new())),
+            path: path.into(),
+            base: Rc::new(RefCell::new(String::new())),
+            history: Rc::new(RefCell::new(History::default())),
+        };
+
+        let view = editor.view.clone();
+        gutter.connect_draw(move |area, cr| {
+            draw_line_numbers(&view, area, cr);
+            Inhibit(false)
+        });
+        if let Some(adjustment) = scrolled.get_vadjustment() {
+            let gutter2 = gutter.clone();
+            adjustment.connect_value_changed(move |_| gutter2.queue_draw());
+        }
+        editor.buffer.connect_changed(move |buffer| {
+            highlight(buffer);
+            gutter.queue_draw();
+        });
+
+        // the footprint is evaluated from the saved file
+        let editor2 = editor.clone();
+        editor
+            .buffer
+            .connect_modified_changed(move |_| editor2.show_status());
+
+        let history = editor.history.clone();
+        editor.buffer.connect_insert_text(move |_, iter, text| {
+            history
+                .borrow_mut()
+                .record(Edit::Insert(iter.get_offset() as usize, text.into()));
+        });
+        let history = editor.history.clone();
+        editor.buffer.connect_delete_range(move |buffer, start, end| {
+            let text = buffer.get_text(start, end, true).unwrap_or_default();
+            history
+                .borrow_mut()
+                .record(Edit::Delete(start.get_offset() as usize, text));
+        });
+
+        let editor2 = editor.clone();
+        editor.view.connect_key_press_event(move |_, event| {
+            if !event.get_state().contains(ModifierType::CONTROL_MASK) {
+                return Inhibit(false);
+            }
+            let key = event.get_keyval();
+            if key == gdk::enums::key::s {
+                editor2.save_with_status();
+            } else if key == gdk::enums::key::z {
+                editor2.undo();
+            } else if key == gdk::enums::key::Z || key == gdk::enums::key::y {
+                editor2.redo();
+            } else {
+                return Inhibit(false);
+            }
+            Inhibit(true)
+        });
+
+        match fs::read_to_string(path) {
+            Ok(data) => editor.load(&data),
+            Err(e) => error!("Error reading {}: {:?}", path.display(), e),
+        }
+        editor
+    }
+
+    pub fn text(&self) -> String {
+        buffer_text(&self.buffer)
+    }
+
+    /// replace the text, keeping the cursor on the same line
+    fn load(&self, data: &str) {
+        let line = self.buffer
+            .get_insert()
+            .map(|mark| self.buffer.get_iter_at_mark(&mark).get_line())
+            .unwrap_or(0);
+        {
+            let mut history = self.history.borrow_mut();
+            history.replaying = true;
+            history.clear();
+        }
+        self.buffer.set_text(data);
+        self.history.borrow_mut().replaying = false;
+        *self.base.borrow_mut() = data.into();
+        self.buffer.set_modified(false);
+        self.buffer.place_cursor(&self.buffer.get_iter_at_line(line));
+    }
+
+    /// write the buffer to the script; the file watcher then evaluates it
+    pub fn save(&self) -> Result<(), MpError> {
+        let text = self.text();
+        fs::write(&self.path, &text)?;
+        info!("saved {}", self.path.display());
+        *self.base.borrow_mut() = text;
+        self.buffer.set_modified(false);
+        Ok(())
+    }
+
+    /// show a status in the statusbar
+    pub fn set_status(&self, status: &str) {
+        *self.status.borrow_mut() = status.into();
+        self.show_status();
+    }
+
+    fn show_status(&self) {
+        let status = self.status.borrow();
+        let status = if !self.buffer.get_modified() {
+            status.clone()
+        } else if status.is_empty() {
+            UNSAVED.into()
+        } else {
+            format!("{} ({})", status, UNSAVED)
+        };
+        self.statusbar.pop(0);
+        self.statusbar.push(0, &status);
+    }
+
+    fn save_with_status(&self) -> bool {
+        let status = match self.save() {
+            Ok(()) => format!("Saved {}", self.path.display()),
+            Err(e) => {
+                error!("{:?}", e);
+                format!("Error saving {}: {:?}", self.path.display(), e)
+            }
+        };
+        self.set_status(&status);
+        !self.buffer.get_modified()
+    }
+
+    /// ask to save unsaved changes before closing; false to stay open
+    pub fn confirm_close(&self, window: &Window) -> bool {
+        if !self.buffer.get_modified() {
+            return true;
+        }
+        let d = MessageDialog::new(
+            Some(window),
+            DialogFlags::MODAL,
+            MessageType::Question,
+            ButtonsType::None,
+            &format!("Save the changes to {}?", self.path.display()),
+        );
+        d.add_button("Close _without saving", ResponseType::No.into());
+        d.add_button("_Cancel", ResponseType::Cancel.into());
+        d.add_button("_Save", ResponseType::Yes.into());
+        let res: ResponseType = d.run().into();
+        d.destroy();
+        match res {
+            ResponseType::No => true,
+            ResponseType::Yes => self.save_with_status(),
+            _ => false,
+        }
+    }
+
+    fn replay(&self, edit: &Edit) {
+        self.history.borrow_mut().replaying = true;
+        self.apply(edit);
+        self.history.borrow_mut().replaying = false;
+    }
+
+    fn apply(&self, edit: &Edit) {
+        match *edit {
+            Edit::Insert(offset, ref text) => {
+                let mut iter = self.buffer.get_iter_at_offset(offset as i32);
+                self.buffer.insert(&mut iter, text);
+                self.buffer.place_cursor(&iter);
+            }
+            Edit::Delete(offset, ref text) => {
+                let mut start = self.buffer.get_iter_at_offset(offset as i32);
+                let mut end = self.buffer
+                    .get_iter_at_offset((offset + text.chars().count()) as i32);
+                self.buffer.delete(&mut start, &mut end);
+                self.buffer.place_cursor(&start);
+            }
+            Edit::Group(ref edits) => {
+                for edit in edits {
+                    self.apply(edit);
+                }
+            }
+        }
+    }
+
+    pub fn undo(&self) {
+        let edit = self.history.borrow_mut().undo.pop();
+        if let Some(edit) = edit {
+            self.replay(&edit.inverse());
+            self.history.borrow_mut().redo.push(edit);
+        }
+    }
+
+    pub fn redo(&self) {
+        let edit = self.history.borrow_mut().redo.pop();
+        if let Some(edit) = edit {
+            self.replay(&edit);
+            self.history.borrow_mut().undo.push(edit);
+        }
+    }
+
+    /// the script on disk changed: show the new version, unless there are
+    /// unsaved changes, then ask whether to reload, merge or keep them
+    pub fn file_changed(&self, data: &str, window: &Window) {
+        let text = self.text();
+        if text == data {
+            *self.base.borrow_mut() = data.into();
+            self.buffer.set_modified(false);
+            return;
+        }
+        if !self.buffer.get_modified() {
+            self.load(data);
+            return;
+        }
+        let d = MessageDialog::new(
+            Some(window),
+            DialogFlags::MODAL,
+            MessageType::Question,
+            ButtonsType::None,
+            &format!(
+                "{} was changed by another program while it has unsaved changes here.",
+                self.path.display()
+            ),
+        );
+        d.add_button("_Reload", RELOAD);
+        d.add_button("_Merge", MERGE);
+        d.add_button("_Keep my changes", KEEP);
+        let res = d.run();
+        d.destroy();
+        match res {
+            RELOAD => self.load(data),
+            MERGE => {
+                let (merged, conflict) = merge(&self.base.borrow(), &text, data);
+                if conflict {
+                    warn!("merge conflicts in {}", self.path.display());
+                }
+                // set_text deletes and inserts, undo both at once
+                self.history.borrow_mut().begin_group();
+                self.buffer.set_text(&merged);
+                self.history.borrow_mut().end_group();
+                *self.base.borrow_mut() = data.into();
+                self.buffer.set_modified(merged != data);
+            }
+            _ => *self.base.borrow_mut() = data.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_clean() {
+        let base = "a\nb\nc\nd\n";
+        let ours = "a\nB\nc\nd\n";
+        let theirs = "a\nb\nc\nD\ne\n";
+        assert_eq!(
+            merge(base, ours, theirs),
+            ("a\nB\nc\nD\ne\n".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn merge_same_change() {
+        let base = "a\nb\n";
+        let ours = "a\nc\n";
+        assert_eq!(merge(base, ours, ours), (ours.to_string(), false));
+    }
+
+    #[test]
+    fn merge_conflict() {
+        let base = "a\nb\nc\n";
+        let ours = "a\nx\nc\n";
+        let theirs = "a\ny\nc\n";
+        assert_eq!(
+            merge(base, ours, theirs),
+            (
+                "a\n<<<<<<< madparts\nx\n=======\ny\n>>>>>>> disk\nc\n".to_string(),
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn merge_without_final_newline() {
+        let base = "a\nb";
+        let ours = "A\nb";
+        let theirs = "a\nb\nc";
+        assert_eq!(merge(base, ours, theirs), ("A\nb\nc".to_string(), false));
+        let (base, ours, theirs) = ("a\nb\nc", "A\nb\nc", "a\nb\nC");
+        assert_eq!(merge(base, ours, theirs), ("A\nb\nC".to_string(), false));
+    }
+
+    #[test]
+    fn merge_conflict_without_final_newline() {
+        let (merged, conflict) = merge("a\nb", "a\nx", "a\ny");
+        assert!(conflict);
+        assert_eq!(merged, "a\n<<<<<<< madparts\nx\n=======\ny\n>>>>>>> disk\n");
+    }
+
+    #[test]
+    fn typing_a_word_is_one_step() {
+        let mut history = History::default();
+        for (i, c) in "def f".chars().enumerate() {
+            history.record(Edit::Insert(i, c.to_string()));
+        }
+        assert_eq!(
+            history.undo,
+            vec![
+                Edit::Insert(0, "def ".into()),
+                Edit::Insert(4, "f".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pasting_is_its_own_step() {
+        let mut history = History::default();
+        history.record(Edit::Insert(0, "a".into()));
+        history.record(Edit::Insert(1, "bc".into()));
+        assert_eq!(
+            history.undo,
+            vec![Edit::Insert(0, "a".into()), Edit::Insert(1, "bc".into())]
+        );
+    }
+
+    #[test]
+    fn deleting_is_one_step() {
+        let mut history = History::default();
+        // backspace over "abc" at 0..3
+        history.record(Edit::Delete(2, "c".into()));
+        history.record(Edit::Delete(1, "b".into()));
+        history.record(Edit::Delete(0, "a".into()));
+        assert_eq!(history.undo, vec![Edit::Delete(0, "abc".into())]);
+        // delete key over "xy" at 5
+        history.record(Edit::Insert(5, " ".into()));
+        history.record(Edit::Delete(5, "x".into()));
+        history.record(Edit::Delete(5, "y".into()));
+        assert_eq!(history.undo.last(), Some(&Edit::Delete(5, "xy".into())));
+    }
+
+    #[test]
+    fn a_group_is_one_step() {
+        let mut history = History::default();
+        history.record(Edit::Insert(0, "a".into()));
+        // what set_text does for a merge
+        history.begin_group();
+        history.record(Edit::Delete(0, "a".into()));
+        history.record(Edit::Insert(0, "b".into()));
+        history.end_group();
+        history.record(Edit::Insert(1, "c".into()));
+        // a group without edits is no undo step
+        history.begin_group();
+        history.end_group();
+        assert_eq!(
+            history.undo,
+            vec![
+                Edit::Insert(0, "a".into()),
+                Edit::Group(vec![Edit::Delete(0, "a".into()), Edit::Insert(0, "b".into())]),
+                Edit::Insert(1, "c".into()),
+            ]
+        );
+        // undone last edit first
+        assert_eq!(
+            history.undo[1].inverse(),
+            Edit::Group(vec![Edit::Delete(0, "b".into()), Edit::Insert(0, "a".into())])
+        );
+    }
+
+    #[test]
+    fn matching_large_files() {
+        let a = (0..5000).map(|i| format!("line {}\n", i)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b[2500] = "changed\n".into();
+        b.insert(4000, "new\n".into());
+        let a = a.iter().map(|l| l.as_str()).collect::<Vec<_>>();
+        let b = b.iter().map(|l| l.as_str()).collect::<Vec<_>>();
+        let m = matching(&a, &b);
+        assert_eq!(m[0], Some(0));
+        assert_eq!(m[2499], Some(2499));
+        assert_eq!(m[2500], None);
+        assert_eq!(m[3999], Some(3999));
+        assert_eq!(m[4000], Some(4001));
+        assert_eq!(m[4999], Some(5000));
+    }
+
+    #[test]
+    fn matching_all_equal_or_empty() {
+        let a = ["a\n", "b\n"];
+        assert_eq!(matching(&a, &a), vec![Some(0), Some(1)]);
+        assert_eq!(matching(&a, &[]), vec![None, None]);
+        assert_eq!(matching(&[], &a), vec![]);
+        // a repeated line must not be matched twice
+        assert_eq!(matching(&["x\n"], &["x\n", "x\n"]), vec![Some(0)]);
+    }
+
+    #[test]
+    fn recording_clears_redo() {
+        let mut history = History::default();
+        history.redo.push(Edit::Insert(0, "a".into()));
+        history.record(Edit::Insert(0, "b".into()));
+        assert!(history.redo.is_empty());
+        history.replaying = true;
+        history.record(Edit::Insert(1, "c".into()));
+        assert_eq!(history.undo, vec![Edit::Insert(0, "b".into())]);
+    }
+}
@@ -2,17 +2,15 @@
 
 use cairo;
 
-use error::MpError;
+use layers::Layer;
+use scheme;
 
-use serde_json;
-
-use layers::{Layer, LAYER};
-
-use std::convert::TryFrom;
+use std::str::FromStr;
 
+use geometry::{normalize_angle, Outline, Point, Transform};
 use kicad;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Bound {
     pub min_x: f64,
     pub min_y: f64,
@@ -21,7 +19,7 @@ pub struct Bound {
 }
 
 impl Bound {
-    fn combine(&self, b: &Bound) -> Bound {
+    pub fn combine(&self, b: &Bound) -> Bound {
         Bound {
             min_x: self.min_x.min(b.min_x),
             min_y: self.min_y.min(b.min_y),
@@ -29,9 +27,63 @@ impl Bound {
             max_y: self.max_y.max(b.max_y),
         }
     }
+
+    pub fn from_points(points: &[Point]) -> Bound {
+        let mut b = Bound {
+            min_x: points[0].x,
+            min_y: points[0].y,
+            max_x: points[0].x,
+            max_y: points[0].y,
+        };
+        for p in &points[1..] {
+            b.min_x = b.min_x.min(p.x);
+            b.min_y = b.min_y.min(p.y);
+            b.max_x = b.max_x.max(p.x);
+            b.max_y = b.max_y.max(p.y);
+        }
+        b
+    }
+
+    fn grow(&self, d: f64) -> Bound {
+        Bound {
+            min_x: self.min_x - d,
+            min_y: self.min_y - d,
+            max_x: self.max_x + d,
+            max_y: self.max_y + d,
+        }
+    }
+}
+
+/// corners of a rectangle of size dx x dy centered on x,y and rotated by rot
+fn rect_corners(x: f64, y: f64, dx: f64, dy: f64, rot: f64) -> [Point; 4] {
+    let t = Transform::rotate(rot).then(&Transform::translate(x, y));
+    [
+        t.apply(Point::new(-dx / 2.0, -dy / 2.0)),
+        t.apply(Point::new(dx / 2.0, -dy / 2.0)),
+        t.apply(Point::new(dx / 2.0, dy / 2.0)),
+        t.apply(Point::new(-dx / 2.0, dy / 2.0)),
+    ]
+}
+
+/// rotate the cairo context by rot degrees around x,y
+fn rotate_around(cr: &cairo::Context, x: f64, y: f64, rot: f64) {
+    cr.translate(x, y);
+    cr.rotate(-rot.to_radians());
+    cr.translate(-x, -y);
+}
+
+/// fill the current path as an opening in a paste or mask layer: an
+/// outline with a light fill, so the copper below stays readable
+fn draw_aperture(cr: &cairo::Context, layer: Layer) {
+    let color = scheme::color(layer);
+    color.with_alpha(0.5).set_source(cr);
+    cr.fill_preserve();
+    color.set_source(cr);
+    cr.set_line_width(0.05);
+    cr.stroke();
 }
 
-trait BoundingBox {
+pub trait BoundingBox {
     fn bounding_box(&self) -> Bound;
 }
 
@@ -43,18 +95,37 @@ pub trait ApplyFootprint {
     fn apply_footprint(&self, &mut kicad::Footprint);
 }
 
-#[derive(Debug, Clone)]
+/// the same as transformed() of the elements in the prelude, which
+/// the scripts use; the tests in python.rs keep the two in line
+#[allow(dead_code)]
+pub trait TransformElement {
+    /// a copy with the transform applied
+    fn transform(&self, t: &Transform) -> Self;
+}
+
+pub trait Inspect {
+    /// kind of element and its name or layer, e.g. "pad 1"
+    fn label(&self) -> String;
+    fn layers(&self) -> Vec<Layer>;
+    /// all properties, as name and value
+    fn properties(&self) -> Vec<(&'static str, String)>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Element {
     Rect(Rect),
     Line(Line),
     Name(Name),
     Reference(Reference),
+    Desc(Desc),
+    Tags(Tags),
     Smd(Smd),
     Pad(Pad),
+    Dimension(Dimension),
     PythonError(PythonError),
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Rect {
     pub x: f64,
     pub y: f64,
@@ -64,9 +135,11 @@ pub struct Rect {
     pub filled: bool,
     pub layer: Layer,
     pub corner: Option<f64>,
+    #[serde(default)]
+    pub rot: f64,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Line {
     pub x1: f64,
     pub y1: f64,
@@ -76,7 +149,7 @@ pub struct Line {
     pub layer: Layer,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Text {
     pub x: f64,
     pub y: f64,
@@ -101,7 +174,7 @@ impl Text {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Smd {
     pub name: String,
     pub x: f64,
@@ -110,16 +183,33 @@ pub struct Smd {
     pub dy: f64,
     pub layers: Vec<Layer>,
     pub shape: SmdShape,
+    #[serde(default)]
+    pub rot: f64,
+    /// the name is meant to be shared with other pads
+    #[serde(default)]
+    pub allow_duplicate: bool,
 }
 
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum SmdShape {
     Rect,
     Circle,
 }
 
+impl FromStr for SmdShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<SmdShape, ()> {
+        match s {
+            "rect" => Ok(SmdShape::Rect),
+            "circle" => Ok(SmdShape::Circle),
+            _ => Err(()),
+        }
+    }
+}
+
 impl Into<&'static str> for SmdShape {
     fn into(self) -> &'static str {
         match self {
@@ -129,7 +219,7 @@ impl Into<&'static str> for SmdShape {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Pad {
     pub name: String,
     pub x: f64,
@@ -139,121 +229,96 @@ pub struct Pad {
     pub drill: f64,
     pub layers: Vec<Layer>,
     pub plated: bool,
+    #[serde(default)]
+    pub rot: f64,
+    /// the name is meant to be shared with other pads
+    #[serde(default)]
+    pub allow_duplicate: bool,
 }
 
-#[derive(Debug, Clone)]
+/// the distance between two points, for fab drawings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Dimension {
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+    /// distance of the dimension line from the points, to the left of
+    /// point 1 to point 2 as seen on screen
+    pub offset: f64,
+    pub w: f64,
+    /// size of the text
+    pub dy: f64,
+    pub layer: Layer,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Name {
     pub text: Text,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Reference {
     pub text: Text,
 }
 
-impl TryFrom<String> for Element {
-    type Error = MpError;
+/// description of the footprint in the kicad library
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Desc {
+    pub txt: String,
+}
 
-    fn try_from(json: String) -> Result<Self, Self::Error> {
-        let v: serde_json::Value = serde_json::from_str(&json)?;
-        match v["t"] {
-            serde_json::Value::Null => {
-                Err(MpError::Other(format!("missing 't' in Element {}", json)))
-            }
-            serde_json::Value::String(ref s) => match s.as_str() {
-                "Rect" | "FFab" | "FCrtYd" | "FPaste" | "FMask" => {
-                    let r: Rect = serde_json::from_str(&json)?;
-                    Ok(Element::Rect(r))
-                }
-                "Line" => {
-                    let r: Line = serde_json::from_str(&json)?;
-                    Ok(Element::Line(r))
-                }
-                "Name" => {
-                    let mut text: Text = serde_json::from_str(&json)?;
-                    text.shorten = Some(true);
-                    Ok(Element::Name(Name { text }))
-                }
-                "Reference" => {
-                    let text: Text = serde_json::from_str(&json)?;
-                    Ok(Element::Reference(Reference { text }))
-                }
-                "Pad" | "Hole" => {
-                    let r: Pad = serde_json::from_str(&json)?;
-                    Ok(Element::Pad(r))
-                }
-                "Smd" => {
-                    let r: Smd = serde_json::from_str(&json)?;
-                    Ok(Element::Smd(r))
-                }
-                "PythonError" => {
-                    let r: PythonError = serde_json::from_str(&json)?;
-                    Ok(Element::PythonError(r))
-                }
-                x => Err(MpError::Other(format!("Unknown type: {}", x))),
-            },
-            _ => Err(MpError::Other(format!("unknown 't' in Element {}", json))),
-        }
-    }
+/// keywords of the footprint in the kicad library, separated by spaces
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tags {
+    pub txt: String,
 }
 
 impl BoundingBox for Line {
     fn bounding_box(&self) -> Bound {
-        let min_x = self.x1.min(self.x2) - self.w / 2.0;
-        let min_y = self.y1.min(self.y2) - self.w / 2.0;
-        let max_x = self.x1.max(self.x2) + self.w / 2.0;
-        let max_y = self.y1.max(self.y2) + self.w / 2.0;
-        Bound {
-            min_x,
-            min_y,
-            max_x,
-            max_y,
-        }
+        Bound::from_points(&[Point::new(self.x1, self.y1), Point::new(self.x2, self.y2)])
+            .grow(self.w / 2.0)
     }
 }
 
 impl BoundingBox for Rect {
     fn bounding_box(&self) -> Bound {
-        let min_x = self.x - self.dx / 2.0 - self.w / 2.0;
-        let max_x = self.x + self.dx / 2.0 + self.w / 2.0;
-        let min_y = self.y - self.dy / 2.0 - self.w / 2.0;
-        let max_y = self.y + self.dy / 2.0 + self.w / 2.0;
-        Bound {
-            min_x,
-            min_y,
-            max_x,
-            max_y,
-        }
+        Bound::from_points(&rect_corners(self.x, self.y, self.dx, self.dy, self.rot))
+            .grow(self.w / 2.0)
     }
 }
 
 impl BoundingBox for Smd {
     fn bounding_box(&self) -> Bound {
-        let min_x = self.x - self.dx / 2.0;
-        let max_x = self.x + self.dx / 2.0;
-        let min_y = self.y - self.dy / 2.0;
-        let max_y = self.y + self.dy / 2.0;
-        Bound {
-            min_x,
-            min_y,
-            max_x,
-            max_y,
-        }
+        Bound::from_points(&rect_corners(self.x, self.y, self.dx, self.dy, self.rot))
     }
 }
 
 impl BoundingBox for Pad {
     fn bounding_box(&self) -> Bound {
-        let min_x = self.x - self.dx / 2.0;
-        let max_x = self.x + self.dx / 2.0;
-        let min_y = self.y - self.dy / 2.0;
-        let max_y = self.y + self.dy / 2.0;
-        Bound {
-            min_x,
-            min_y,
-            max_x,
-            max_y,
+        Bound::from_points(&rect_corners(self.x, self.y, self.dx, self.dy, self.rot))
+    }
+}
+
+impl BoundingBox for Dimension {
+    fn bounding_box(&self) -> Bound {
+        let mut points = vec![Point::new(self.x1, self.y1), Point::new(self.x2, self.y2)];
+        for (a, b) in self.segments() {
+            points.push(a);
+            points.push(b);
+        }
+        // the text is about 0.6 dy wide per character
+        let (p, rot, text) = self.label();
+        let half = text.len() as f64 * self.dy * 0.3;
+        let (ux, uy) = (rot.to_radians().cos(), -rot.to_radians().sin());
+        for &(along, across) in &[(half, 0.5), (-half, 0.5), (half, -0.5), (-half, -0.5)] {
+            let across = across * self.dy;
+            points.push(Point::new(
+                p.x + ux * along - uy * across,
+                p.y + uy * along + ux * across,
+            ));
         }
+        Bound::from_points(&points).grow(self.w / 2.0)
     }
 }
 
@@ -297,8 +362,11 @@ impl BoundingBox for Element {
             Element::Rect(ref r) => r.bounding_box(),
             Element::Name(ref t) => t.text.bounding_box(),
             Element::Reference(ref t) => t.text.bounding_box(),
+            // not drawn, so no size of their own
+            Element::Desc(_) | Element::Tags(_) => Bound::default(),
             Element::Smd(ref r) => r.bounding_box(),
             Element::Pad(ref r) => r.bounding_box(),
+            Element::Dimension(ref r) => r.bounding_box(),
             Element::PythonError(_) => unreachable!(),
         }
     }
@@ -307,7 +375,7 @@ impl BoundingBox for Element {
 impl DrawElement for Line {
     fn draw_element(&self, cr: &cairo::Context, layer: Layer) {
         if layer == self.layer {
-            LAYER[&layer].color.set_source(cr);
+            scheme::color(layer).set_source(cr);
             cr.set_line_width(self.w);
             cr.set_line_cap(cairo::enums::LineCap::Round);
             cr.move_to(self.x1, self.y1);
@@ -320,7 +388,9 @@ impl DrawElement for Line {
 impl DrawElement for Rect {
     fn draw_element(&self, cr: &cairo::Context, layer: Layer) {
         if layer == self.layer {
-            LAYER[&layer].color.set_source(cr);
+            cr.save();
+            rotate_around(cr, self.x, self.y, self.rot);
+            scheme::color(layer).set_source(cr);
             if self.filled {
                 cr.rectangle(
                     self.x - self.dx / 2.0,
@@ -344,28 +414,34 @@ impl DrawElement for Rect {
                 cr.close_path();
                 cr.stroke();
             }
+            cr.restore();
         }
     }
 }
 
 impl DrawElement for Smd {
+    /// the copper with the pad name, and the apertures on the paste and
+    /// mask layers
     fn draw_element(&self, cr: &cairo::Context, layer: Layer) {
-        if layer == Layer::FCu {
-            LAYER[&layer].color.set_source(cr);
-            match self.shape {
-                SmdShape::Rect =>
-                    cr.rectangle(
-                        self.x - self.dx / 2.0,
-                        self.y - self.dy / 2.0,
-                        self.dx,
-                        self.dy,
-                    ),
-                SmdShape::Circle => {
-                    cr.stroke();
-                    cr.set_line_width(0.0);
-                    cr.arc(self.x, self.y, self.dx / 2.0, 0.0, 360.0);
-                },
-            };
+        if !self.layers.iter().any(|l| l.shows_on(layer)) {
+            return;
+        }
+        cr.save();
+        rotate_around(cr, self.x, self.y, self.rot);
+        match self.shape {
+            SmdShape::Rect =>
+                cr.rectangle(
+                    self.x - self.dx / 2.0,
+                    self.y - self.dy / 2.0,
+                    self.dx,
+                    self.dy,
+                ),
+            SmdShape::Circle => cr.arc(self.x, self.y, self.dx / 2.0, 0.0, 360.0),
+        };
+        if layer != self.copper_layer() {
+            draw_aperture(cr, layer);
+        } else {
+            scheme::color(layer).set_source(cr);
             cr.fill();
             cr.select_font_face(
                 "Sans",
@@ -381,13 +457,16 @@ impl DrawElement for Smd {
             cr.move_to(self.x - w / 2.0 - ext.x_bearing, self.y + h / 2.0);
             cr.show_text(&self.name);
         }
+        cr.restore();
     }
 }
 
 impl DrawElement for Pad {
     fn draw_element(&self, cr: &cairo::Context, layer: Layer) {
-        if layer == Layer::FCu {
-            LAYER[&layer].color.set_source(cr);
+        if layer == Layer::Cu {
+            cr.save();
+            rotate_around(cr, self.x, self.y, self.rot);
+            scheme::color(layer).set_source(cr);
             // TODO: other types then circle
             cr.stroke();
             cr.set_line_width(0.0);
@@ -422,6 +501,41 @@ impl DrawElement for Pad {
             let h = ext.height;
             cr.move_to(self.x - w / 2.0 - ext.x_bearing, self.y + h / 2.0);
             cr.show_text(&self.name);
+            cr.restore();
+        } else if self.layers.iter().any(|l| *l != Layer::Cu && l.shows_on(layer)) {
+            cr.save();
+            rotate_around(cr, self.x, self.y, self.rot);
+            cr.arc(self.x, self.y, self.dx / 2.0, 0.0, 360.0);
+            draw_aperture(cr, layer);
+            cr.restore();
+        }
+    }
+}
+
+impl DrawElement for Dimension {
+    fn draw_element(&self, cr: &cairo::Context, layer: Layer) {
+        if layer == self.layer {
+            scheme::color(layer).set_source(cr);
+            cr.set_line_width(self.w);
+            cr.set_line_cap(cairo::enums::LineCap::Round);
+            for (a, b) in self.segments() {
+                cr.move_to(a.x, a.y);
+                cr.line_to(b.x, b.y);
+            }
+            cr.stroke();
+            let (p, rot, text) = self.label();
+            cr.save();
+            rotate_around(cr, p.x, p.y, rot);
+            cr.select_font_face(
+                "Sans",
+                cairo::enums::FontSlant::Normal,
+                cairo::enums::FontWeight::Normal,
+            );
+            cr.set_font_size(self.dy);
+            let ext = cr.text_extents(&text);
+            cr.move_to(p.x - ext.width / 2.0 - ext.x_bearing, p.y + ext.height / 2.0);
+            cr.show_text(&text);
+            cr.restore();
         }
     }
 }
@@ -441,7 +555,7 @@ impl DrawElement for Text {
         let h = ext.height;
         //cr.rectangle(self.x-w/2.0, self.y-h/2.0, w, h);
         //cr.fill();
-        LAYER[&layer].color.set_source(cr);
+        scheme::color(layer).set_source(cr);
         cr.move_to(self.x - w / 2.0 - ext.x_bearing, self.y + h / 2.0);
         cr.show_text(&text);
     }
@@ -470,16 +584,174 @@ impl DrawElement for Element {
             Element::Rect(ref r) => r.draw_element(cr, layer),
             Element::Name(ref t) => t.draw_element(cr, layer),
             Element::Reference(ref t) => t.draw_element(cr, layer),
+            Element::Desc(_) | Element::Tags(_) => (),
             Element::Smd(ref t) => t.draw_element(cr, layer),
             Element::Pad(ref t) => t.draw_element(cr, layer),
+            Element::Dimension(ref t) => t.draw_element(cr, layer),
             Element::PythonError(_) => unreachable!(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PythonError {
+    /// the complete traceback
     pub message: String,
+    /// the exception itself, without the traceback
+    #[serde(default)]
+    pub exception: String,
+    /// frames in the script and its helper modules, innermost last
+    #[serde(default)]
+    pub frames: Vec<Frame>,
+}
+
+/// one traceback frame
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Frame {
+    pub filename: String,
+    pub line: usize,
+    pub function: String,
+}
+
+impl PythonError {
+    /// error without any traceback
+    pub fn new(message: String) -> PythonError {
+        PythonError {
+            exception: message.clone(),
+            message,
+            frames: vec![],
+        }
+    }
+}
+
+impl Rect {
+    pub fn corners(&self) -> [Point; 4] {
+        rect_corners(self.x, self.y, self.dx, self.dy, self.rot)
+    }
+}
+
+impl Smd {
+    /// the side the pad is on, as a copper layer
+    pub fn copper_layer(&self) -> Layer {
+        if self.layers.contains(&Layer::BCu) {
+            Layer::BCu
+        } else {
+            Layer::FCu
+        }
+    }
+
+    /// copper outline, for clearance checks
+    pub fn outline(&self) -> Outline {
+        match self.shape {
+            SmdShape::Rect => {
+                Outline::Polygon(rect_corners(self.x, self.y, self.dx, self.dy, self.rot))
+            }
+            SmdShape::Circle => Outline::Circle(Point::new(self.x, self.y), self.dx / 2.0),
+        }
+    }
+}
+
+impl Pad {
+    /// copper outline, for clearance checks; pads are round for now
+    pub fn outline(&self) -> Outline {
+        Outline::Circle(Point::new(self.x, self.y), self.dx / 2.0)
+    }
+}
+
+impl Dimension {
+    pub fn length(&self) -> f64 {
+        Point::new(self.x1, self.y1).distance(&Point::new(self.x2, self.y2))
+    }
+
+    /// unit vector from point 1 to point 2 and the unit normal on the side
+    /// of a positive offset
+    fn direction(&self) -> (Point, Point) {
+        let l = self.length();
+        let d = Point::new((self.x2 - self.x1) / l, (self.y2 - self.y1) / l);
+        (d, Point::new(d.y, -d.x))
+    }
+
+    /// the dimension line, the extension lines and the arrow heads
+    pub fn segments(&self) -> Vec<(Point, Point)> {
+        let length = self.length();
+        if length == 0.0 {
+            return vec![];
+        }
+        let (d, n) = self.direction();
+        let at = |x: f64, y: f64, along: f64, across: f64| {
+            Point::new(
+                x + d.x * along + n.x * across,
+                y + d.y * along + n.y * across,
+            )
+        };
+        let arrow = (length / 4.0).min(self.dy * 0.5);
+        // the extension lines go a bit past the dimension line
+        let overshoot = self.offset + self.offset.signum() * arrow / 2.0;
+        let a = at(self.x1, self.y1, 0.0, self.offset);
+        let b = at(self.x2, self.y2, 0.0, self.offset);
+        vec![
+            (a, b),
+            (Point::new(self.x1, self.y1), at(self.x1, self.y1, 0.0, overshoot)),
+            (Point::new(self.x2, self.y2), at(self.x2, self.y2, 0.0, overshoot)),
+            (a, at(a.x, a.y, arrow, arrow / 3.0)),
+            (a, at(a.x, a.y, arrow, -arrow / 3.0)),
+            (b, at(b.x, b.y, -arrow, arrow / 3.0)),
+            (b, at(b.x, b.y, -arrow, -arrow / 3.0)),
+        ]
+    }
+
+    /// position, rotation and text of the value; the text is next to the
+    /// middle of the dimension line, along the line and readable
+    pub fn label(&self) -> (Point, f64, String) {
+        let text = format!("{:.2} mm", self.length());
+        let mx = (self.x1 + self.x2) / 2.0;
+        let my = (self.y1 + self.y2) / 2.0;
+        if self.length() == 0.0 {
+            return (Point::new(mx, my), 0.0, text);
+        }
+        let (d, n) = self.direction();
+        let side = if self.offset < 0.0 { -1.0 } else { 1.0 };
+        let across = self.offset + side * self.dy * 0.7;
+        let p = Point::new(mx + n.x * across, my + n.y * across);
+        let mut rot = -d.y.atan2(d.x).to_degrees();
+        if rot > 90.0 {
+            rot -= 180.0;
+        } else if rot <= -90.0 {
+            rot += 180.0;
+        }
+        (p, rot, text)
+    }
+}
+
+/// text of the Name element, as used for the footprint name
+pub fn footprint_name(v: &Vec<Element>) -> String {
+    for e in v {
+        match e {
+            Element::Name(x) => return x.text.txt.clone(),
+            _ => (),
+        }
+    }
+    "NAME".into()
+}
+
+/// the footprint name, made safe to use as a file name in a directory
+pub fn file_name(v: &Vec<Element>) -> String {
+    let name = footprint_name(v)
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    // no hidden files, and no "." or ".."
+    let dots = name.chars().take_while(|&c| c == '.').count();
+    let name = format!("{}{}", "_".repeat(dots), &name[dots..]);
+    if name.is_empty() {
+        "NAME".into()
+    } else {
+        name
+    }
 }
 
 pub fn bound(v: &Vec<Element>) -> Bound {
@@ -490,6 +762,33 @@ pub fn bound(v: &Vec<Element>) -> Bound {
     s
 }
 
+/// index of the bound at p, within tolerance; if several elements are
+/// there, the one with the smallest bounding box, so a pad wins from the
+/// outline around it
+pub fn element_at<F: Fn(usize) -> bool>(
+    bounds: &[Bound],
+    p: Point,
+    tolerance: f64,
+    include: F,
+) -> Option<usize> {
+    let mut found: Option<(usize, f64)> = None;
+    for (i, b) in bounds.iter().enumerate() {
+        if !include(i) {
+            continue;
+        }
+        let b = b.grow(tolerance);
+        if p.x < b.min_x || p.x > b.max_x || p.y < b.min_y || p.y > b.max_y {
+            continue;
+        }
+        let area = (b.max_x - b.min_x) * (b.max_y - b.min_y);
+        match found {
+            Some((_, a)) if a <= area => (),
+            _ => found = Some((i, area)),
+        }
+    }
+    found.map(|(i, _)| i)
+}
+
 impl ApplyFootprint for Element {
     fn apply_footprint(&self, f: &mut kicad::Footprint) {
         match *self {
@@ -497,8 +796,11 @@ impl ApplyFootprint for Element {
             Element::Line(ref e) => e.apply_footprint(f),
             Element::Name(ref e) => e.apply_footprint(f),
             Element::Reference(ref e) => e.apply_footprint(f),
+            Element::Desc(ref e) => f.desc = e.txt.clone(),
+            Element::Tags(ref e) => f.tags = e.txt.clone(),
             Element::Smd(ref e) => e.apply_footprint(f),
             Element::Pad(ref e) => e.apply_footprint(f),
+            Element::Dimension(ref e) => e.apply_footprint(f),
             Element::PythonError(_) => unreachable!(),
         }
     }
@@ -510,38 +812,18 @@ impl ApplyFootprint for Rect {
             f.rects.push(self.clone());
         } else {
             // TODO: corner export
-            f.lines.push(Line {
-                x1: self.x - self.dx / 2.0,
-                y1: self.y - self.dy / 2.0,
-                x2: self.x + self.dx / 2.0,
-                y2: self.y - self.dy / 2.0,
-                w: self.w,
-                layer: self.layer,
-            });
-            f.lines.push(Line {
-                x1: self.x + self.dx / 2.0,
-                y1: self.y - self.dy / 2.0,
-                x2: self.x + self.dx / 2.0,
-                y2: self.y + self.dy / 2.0,
-                w: self.w,
-                layer: self.layer,
-            });
-            f.lines.push(Line {
-                x1: self.x + self.dx / 2.0,
-                y1: self.y + self.dy / 2.0,
-                x2: self.x - self.dx / 2.0,
-                y2: self.y + self.dy / 2.0,
-                w: self.w,
-                layer: self.layer,
-            });
-            f.lines.push(Line {
-                x1: self.x - self.dx / 2.0,
-                y1: self.y + self.dy / 2.0,
-                x2: self.x - self.dx / 2.0,
-                y2: self.y - self.dy / 2.0,
-                w: self.w,
-                layer: self.layer,
-            });
+            let c = self.corners();
+            for i in 0..4 {
+                let (p1, p2) = (c[i], c[(i + 1) % 4]);
+                f.lines.push(Line {
+                    x1: p1.x,
+                    y1: p1.y,
+                    x2: p2.x,
+                    y2: p2.y,
+                    w: self.w,
+                    layer: self.layer,
+                });
+            }
         }
     }
 }
@@ -575,3 +857,380 @@ impl ApplyFootprint for Pad {
         f.pad.push(self.clone())
     }
 }
+
+impl ApplyFootprint for Dimension {
+    fn apply_footprint(&self, f: &mut kicad::Footprint) {
+        f.dimensions.push(self.clone())
+    }
+}
+
+impl TransformElement for Element {
+    fn transform(&self, t: &Transform) -> Element {
+        match *self {
+            Element::Rect(ref e) => Element::Rect(e.transform(t)),
+            Element::Line(ref e) => Element::Line(e.transform(t)),
+            Element::Name(ref e) => Element::Name(Name {
+                text: e.text.transform(t),
+            }),
+            Element::Reference(ref e) => Element::Reference(Reference {
+                text: e.text.transform(t),
+            }),
+            Element::Smd(ref e) => Element::Smd(e.transform(t)),
+            Element::Pad(ref e) => Element::Pad(e.transform(t)),
+            Element::Dimension(ref e) => Element::Dimension(e.transform(t)),
+            Element::Desc(_) | Element::Tags(_) | Element::PythonError(_) => self.clone(),
+        }
+    }
+}
+
+impl TransformElement for Rect {
+    fn transform(&self, t: &Transform) -> Rect {
+        let p = t.apply(Point::new(self.x, self.y));
+        let s = t.scale_factor();
+        let mut r = Rect {
+            x: p.x,
+            y: p.y,
+            dx: self.dx * s,
+            dy: self.dy * s,
+            rot: t.apply_rotation(self.rot),
+            corner: self.corner.map(|c| c * s),
+            ..self.clone()
+        };
+        if r.corner.is_some() && t.is_mirrored() {
+            // the chamfer is always drawn in the top-left corner;
+            // a mirrored rect is the same rect with dx and dy swapped,
+            // rotated by an extra -90 degrees
+            ::std::mem::swap(&mut r.dx, &mut r.dy);
+            r.rot = normalize_angle(r.rot - 90.0);
+        }
+        r
+    }
+}
+
+impl TransformElement for Line {
+    fn transform(&self, t: &Transform) -> Line {
+        let p1 = t.apply(Point::new(self.x1, self.y1));
+        let p2 = t.apply(Point::new(self.x2, self.y2));
+        Line {
+            x1: p1.x,
+            y1: p1.y,
+            x2: p2.x,
+            y2: p2.y,
+            ..self.clone()
+        }
+    }
+}
+
+impl TransformElement for Dimension {
+    fn transform(&self, t: &Transform) -> Dimension {
+        let p1 = t.apply(Point::new(self.x1, self.y1));
+        let p2 = t.apply(Point::new(self.x2, self.y2));
+        let offset = self.offset * t.scale_factor();
+        Dimension {
+            x1: p1.x,
+            y1: p1.y,
+            x2: p2.x,
+            y2: p2.y,
+            // mirroring swaps left and right
+            offset: if t.is_mirrored() { -offset } else { offset },
+            ..self.clone()
+        }
+    }
+}
+
+impl TransformElement for Text {
+    fn transform(&self, t: &Transform) -> Text {
+        // text stays upright and keeps its size
+        let p = t.apply(Point::new(self.x, self.y));
+        Text {
+            x: p.x,
+            y: p.y,
+            ..self.clone()
+        }
+    }
+}
+
+impl TransformElement for Smd {
+    fn transform(&self, t: &Transform) -> Smd {
+        let p = t.apply(Point::new(self.x, self.y));
+        let s = t.scale_factor();
+        Smd {
+            x: p.x,
+            y: p.y,
+            dx: self.dx * s,
+            dy: self.dy * s,
+            rot: t.apply_rotation(self.rot),
+            ..self.clone()
+        }
+    }
+}
+
+impl TransformElement for Pad {
+    fn transform(&self, t: &Transform) -> Pad {
+        let p = t.apply(Point::new(self.x, self.y));
+        let s = t.scale_factor();
+        Pad {
+            x: p.x,
+            y: p.y,
+            dx: self.dx * s,
+            dy: self.dy * s,
+            drill: self.drill * s,
+            rot: t.apply_rotation(self.rot),
+            ..self.clone()
+        }
+    }
+}
+
+fn layer_names(layers: &[Layer]) -> String {
+    layers
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+impl Inspect for Element {
+    fn label(&self) -> String {
+        match *self {
+            Element::Rect(ref e) => e.label(),
+            Element::Line(ref e) => e.label(),
+            Element::Name(ref e) => format!("name {}", e.text.txt),
+            Element::Reference(ref e) => format!("reference {}", e.text.txt),
+            Element::Desc(_) => "description".into(),
+            Element::Tags(_) => "keywords".into(),
+            Element::Smd(ref e) => e.label(),
+            Element::Pad(ref e) => e.label(),
+            Element::Dimension(ref e) => e.label(),
+            Element::PythonError(_) => "error".into(),
+        }
+    }
+
+    fn layers(&self) -> Vec<Layer> {
+        match *self {
+            Element::Rect(ref e) => e.layers(),
+            Element::Line(ref e) => e.layers(),
+            Element::Name(ref e) => e.text.layers(),
+            Element::Reference(ref e) => e.text.layers(),
+            Element::Desc(_) | Element::Tags(_) => vec![],
+            Element::Smd(ref e) => e.layers(),
+            Element::Pad(ref e) => e.layers(),
+            Element::Dimension(ref e) => e.layers(),
+            Element::PythonError(_) => vec![],
+        }
+    }
+
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        let mut v = vec![];
+        match *self {
+            Element::Rect(ref e) => {
+                v.push(("type", "rect".into()));
+                v.extend(e.properties());
+            }
+            Element::Line(ref e) => {
+                v.push(("type", "line".into()));
+                v.extend(e.properties());
+            }
+            Element::Name(ref e) => {
+                v.push(("type", "name".into()));
+                v.extend(e.text.properties());
+            }
+            Element::Reference(ref e) => {
+                v.push(("type", "reference".into()));
+                v.extend(e.text.properties());
+            }
+            Element::Desc(ref e) => {
+                v.push(("type", "description".into()));
+                v.push(("text", e.txt.clone()));
+            }
+            Element::Tags(ref e) => {
+                v.push(("type", "keywords".into()));
+                v.push(("text", e.txt.clone()));
+            }
+            Element::Smd(ref e) => {
+                v.push(("type", "smd".into()));
+                v.extend(e.properties());
+            }
+            Element::Pad(ref e) => {
+                v.push(("type", "pad".into()));
+                v.extend(e.properties());
+            }
+            Element::Dimension(ref e) => {
+                v.push(("type", "dimension".into()));
+                v.extend(e.properties());
+            }
+            Element::PythonError(ref e) => v.push(("error", e.exception.clone())),
+        }
+        v
+    }
+}
+
+impl Inspect for Rect {
+    fn label(&self) -> String {
+        format!("rect {}", self.layer)
+    }
+
+    fn layers(&self) -> Vec<Layer> {
+        vec![self.layer]
+    }
+
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        let mut v = vec![
+            ("position", format!("{}, {}", self.x, self.y)),
+            ("size", format!("{} x {}", self.dx, self.dy)),
+            ("rotation", format!("{}", self.rot)),
+            ("width", format!("{}", self.w)),
+            ("filled", format!("{}", self.filled)),
+        ];
+        if let Some(corner) = self.corner {
+            v.push(("corner", format!("{}", corner)));
+        }
+        v.push(("layers", self.layer.to_string()));
+        v
+    }
+}
+
+impl Inspect for Line {
+    fn label(&self) -> String {
+        format!("line {}", self.layer)
+    }
+
+    fn layers(&self) -> Vec<Layer> {
+        vec![self.layer]
+    }
+
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("start", format!("{}, {}", self.x1, self.y1)),
+            ("end", format!("{}, {}", self.x2, self.y2)),
+            ("width", format!("{}", self.w)),
+            ("layers", self.layer.to_string()),
+        ]
+    }
+}
+
+impl Inspect for Text {
+    fn label(&self) -> String {
+        format!("text {}", self.txt)
+    }
+
+    fn layers(&self) -> Vec<Layer> {
+        vec![self.layer]
+    }
+
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("text", self.txt.clone()),
+            ("position", format!("{}, {}", self.x, self.y)),
+            ("size", format!("{}", self.dy)),
+            ("thickness", format!("{}", self.thickness)),
+            ("layers", self.layer.to_string()),
+        ]
+    }
+}
+
+impl Inspect for Smd {
+    fn label(&self) -> String {
+        format!("pad {}", self.name)
+    }
+
+    fn layers(&self) -> Vec<Layer> {
+        self.layers.clone()
+    }
+
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        let shape: &'static str = self.shape.clone().into();
+        vec![
+            ("name", self.name.clone()),
+            ("position", format!("{}, {}", self.x, self.y)),
+            ("size", format!("{} x {}", self.dx, self.dy)),
+            ("rotation", format!("{}", self.rot)),
+            ("shape", shape.into()),
+            ("layers", layer_names(&self.layers)),
+        ]
+    }
+}
+
+impl Inspect for Pad {
+    fn label(&self) -> String {
+        format!("pad {}", self.name)
+    }
+
+    fn layers(&self) -> Vec<Layer> {
+        self.layers.clone()
+    }
+
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", self.name.clone()),
+            ("position", format!("{}, {}", self.x, self.y)),
+            ("size", format!("{} x {}", self.dx, self.dy)),
+            ("rotation", format!("{}", self.rot)),
+            ("shape", "circle".into()),
+            ("drill", format!("{}", self.drill)),
+            ("plated", format!("{}", self.plated)),
+            ("layers", layer_names(&self.layers)),
+        ]
+    }
+}
+
+impl Inspect for Dimension {
+    fn label(&self) -> String {
+        format!("dimension {:.2} mm", self.length())
+    }
+
+    fn layers(&self) -> Vec<Layer> {
+        vec![self.layer]
+    }
+
+    fn properties(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("start", format!("{}, {}", self.x1, self.y1)),
+            ("end", format!("{}, {}", self.x2, self.y2)),
+            ("offset", format!("{}", self.offset)),
+            ("value", format!("{:.3} mm", self.length())),
+            ("width", format!("{}", self.w)),
+            ("size", format!("{}", self.dy)),
+            ("layers", self.layer.to_string()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str) -> Vec<Element> {
+        vec![Element::Name(Name {
+            text: Text {
+                x: 0.0,
+                y: 0.0,
+                dy: 1.0,
+                txt: name.into(),
+                thickness: 0.15,
+                shorten: None,
+                layer: Layer::FFab,
+            },
+        })]
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(file_name(&named("SOIC-8_3.9x4.9mm")), "SOIC-8_3.9x4.9mm");
+        assert_eq!(file_name(&vec![]), "NAME");
+        assert_eq!(file_name(&named("")), "NAME");
+    }
+
+    #[test]
+    fn file_names_stay_in_the_directory() {
+        assert_eq!(file_name(&named("../x")), "___x");
+        assert_eq!(file_name(&named("..\\x")), "___x");
+        assert_eq!(file_name(&named("/etc/passwd")), "_etc_passwd");
+        assert_eq!(file_name(&named("C:\\x")), "C:_x");
+        assert_eq!(file_name(&named("a/b\\c")), "a_b_c");
+        assert_eq!(file_name(&named(".")), "_");
+        assert_eq!(file_name(&named("..")), "__");
+        assert_eq!(file_name(&named(".hidden")), "_hidden");
+        assert_eq!(file_name(&named("a..b.")), "a..b.");
+        assert_eq!(file_name(&named("a\nb\0")), "a_b_");
+    }
+}
@@ -8,7 +8,7 @@ use serde_json;
 
 // TODO: use failure crate
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum MpError {
     GuiError(String),
     IOError(String),
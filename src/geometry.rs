@@ -0,0 +1,431 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Coordinates follow KiCad and cairo: x points right, y points down.
+// Angles are in degrees and positive angles rotate counter-clockwise
+// as seen on screen, which is what KiCad uses for `(at x y rot)`.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    pub fn distance(&self, p: &Point) -> f64 {
+        ((self.x - p.x).powi(2) + (self.y - p.y).powi(2)).sqrt()
+    }
+}
+
+/// Affine transformation, with the same layout as a cairo matrix:
+///
+/// x' = xx * x + xy * y + x0
+/// y' = yx * x + yy * y + y0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub xx: f64,
+    pub yx: f64,
+    pub xy: f64,
+    pub yy: f64,
+    pub x0: f64,
+    pub y0: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            xx: 1.0,
+            yx: 0.0,
+            xy: 0.0,
+            yy: 1.0,
+            x0: 0.0,
+            y0: 0.0,
+        }
+    }
+
+    pub fn translate(dx: f64, dy: f64) -> Transform {
+        Transform {
+            x0: dx,
+            y0: dy,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn rotate(deg: f64) -> Transform {
+        let (s, c) = deg.to_radians().sin_cos();
+        Transform {
+            xx: c,
+            yx: -s,
+            xy: s,
+            yy: c,
+            x0: 0.0,
+            y0: 0.0,
+        }
+    }
+
+    /// mirror around the y axis, e.g. x becomes -x
+    pub fn mirror_x() -> Transform {
+        Transform {
+            xx: -1.0,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn scale(s: f64) -> Transform {
+        Transform {
+            xx: s,
+            yy: s,
+            ..Transform::identity()
+        }
+    }
+
+    /// combine two transforms: first apply self, then other
+    pub fn then(&self, o: &Transform) -> Transform {
+        Transform {
+            xx: o.xx * self.xx + o.xy * self.yx,
+            yx: o.yx * self.xx + o.yy * self.yx,
+            xy: o.xx * self.xy + o.xy * self.yy,
+            yy: o.yx * self.xy + o.yy * self.yy,
+            x0: o.xx * self.x0 + o.xy * self.y0 + o.x0,
+            y0: o.yx * self.x0 + o.yy * self.y0 + o.y0,
+        }
+    }
+
+    pub fn apply(&self, p: Point) -> Point {
+        Point {
+            x: self.xx * p.x + self.xy * p.y + self.x0,
+            y: self.yx * p.x + self.yy * p.y + self.y0,
+        }
+    }
+
+    /// the transform that undoes this one; it has to be invertible
+    pub fn invert(&self) -> Transform {
+        let d = self.determinant();
+        let xx = self.yy / d;
+        let yx = -self.yx / d;
+        let xy = -self.xy / d;
+        let yy = self.xx / d;
+        Transform {
+            xx,
+            yx,
+            xy,
+            yy,
+            x0: -(xx * self.x0 + xy * self.y0),
+            y0: -(yx * self.x0 + yy * self.y0),
+        }
+    }
+
+    fn determinant(&self) -> f64 {
+        self.xx * self.yy - self.xy * self.yx
+    }
+
+    pub fn is_mirrored(&self) -> bool {
+        self.determinant() < 0.0
+    }
+
+    /// uniform scale factor; sizes of elements are multiplied by this
+    pub fn scale_factor(&self) -> f64 {
+        self.determinant().abs().sqrt()
+    }
+
+    /// rotation part of the transform in degrees, after taking out
+    /// the mirroring if any
+    pub fn rotation(&self) -> f64 {
+        let a = if self.is_mirrored() {
+            self.yx.atan2(-self.xx)
+        } else {
+            (-self.yx).atan2(self.xx)
+        };
+        normalize_angle(a.to_degrees())
+    }
+
+    /// new rotation of an element with rotation `rot` after applying this
+    /// transform, ignoring any shape change caused by mirroring
+    pub fn apply_rotation(&self, rot: f64) -> f64 {
+        if self.is_mirrored() {
+            normalize_angle(self.rotation() - rot)
+        } else {
+            normalize_angle(self.rotation() + rot)
+        }
+    }
+}
+
+/// bring an angle in degrees in the [0, 360) range
+pub fn normalize_angle(a: f64) -> f64 {
+    let a = a % 360.0;
+    if a < 0.0 {
+        a + 360.0
+    } else {
+        a
+    }
+}
+
+/// point on the segment a-b closest to p
+pub fn closest_point_on_segment(p: Point, a: Point, b: Point) -> Point {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let l2 = dx * dx + dy * dy;
+    if l2 == 0.0 {
+        return a;
+    }
+    let t = (((p.x - a.x) * dx + (p.y - a.y) * dy) / l2).max(0.0).min(1.0);
+    Point::new(a.x + t * dx, a.y + t * dy)
+}
+
+/// shortest distance from p to the segment a-b
+pub fn point_segment_distance(p: Point, a: Point, b: Point) -> f64 {
+    p.distance(&closest_point_on_segment(p, a, b))
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f64 {
+    (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+}
+
+fn segments_intersect(a1: Point, a2: Point, b1: Point, b2: Point) -> bool {
+    let d1 = cross(b1, b2, a1);
+    let d2 = cross(b1, b2, a2);
+    let d3 = cross(a1, a2, b1);
+    let d4 = cross(a1, a2, b2);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// shortest distance between the segments a1-a2 and b1-b2
+pub fn segment_distance(a1: Point, a2: Point, b1: Point, b2: Point) -> f64 {
+    if segments_intersect(a1, a2, b1, b2) {
+        return 0.0;
+    }
+    point_segment_distance(a1, b1, b2)
+        .min(point_segment_distance(a2, b1, b2))
+        .min(point_segment_distance(b1, a1, a2))
+        .min(point_segment_distance(b2, a1, a2))
+}
+
+/// true if p is inside the convex polygon
+pub fn polygon_contains(polygon: &[Point], p: Point) -> bool {
+    let n = polygon.len();
+    let sides = (0..n)
+        .map(|i| cross(polygon[i], polygon[(i + 1) % n], p))
+        .collect::<Vec<f64>>();
+    if sides.iter().all(|&s| s == 0.0) {
+        // a polygon without an area, like a zero size pad, only contains
+        // the points on its edges
+        return (0..n)
+            .any(|i| point_segment_distance(p, polygon[i], polygon[(i + 1) % n]) == 0.0);
+    }
+    sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0)
+}
+
+/// shortest distance between the segment a-b and the convex polygon,
+/// 0 if they overlap
+pub fn segment_polygon_distance(a: Point, b: Point, polygon: &[Point]) -> f64 {
+    if polygon_contains(polygon, a) || polygon_contains(polygon, b) {
+        return 0.0;
+    }
+    let n = polygon.len();
+    (0..n)
+        .map(|i| segment_distance(a, b, polygon[i], polygon[(i + 1) % n]))
+        .fold(::std::f64::INFINITY, f64::min)
+}
+
+/// outline of a pad, for clearance checks
+#[derive(Debug, Clone, Copy)]
+pub enum Outline {
+    /// convex polygon
+    Polygon([Point; 4]),
+    /// center and radius
+    Circle(Point, f64),
+}
+
+fn polygon_point_distance(polygon: &[Point], p: Point) -> f64 {
+    if polygon_contains(polygon, p) {
+        return 0.0;
+    }
+    let n = polygon.len();
+    (0..n)
+        .map(|i| point_segment_distance(p, polygon[i], polygon[(i + 1) % n]))
+        .fold(::std::f64::INFINITY, f64::min)
+}
+
+impl Outline {
+    /// shortest distance to the segment a-b, 0 if they overlap
+    pub fn segment_distance(&self, a: Point, b: Point) -> f64 {
+        match *self {
+            Outline::Polygon(ref p) => segment_polygon_distance(a, b, p),
+            Outline::Circle(c, r) => (point_segment_distance(c, a, b) - r).max(0.0),
+        }
+    }
+
+    /// point on the edge of the outline closest to p
+    pub fn closest_edge_point(&self, p: Point) -> Point {
+        match *self {
+            Outline::Polygon(ref q) => {
+                let mut best = q[0];
+                for i in 0..4 {
+                    let c = closest_point_on_segment(p, q[i], q[(i + 1) % 4]);
+                    if c.distance(&p) < best.distance(&p) {
+                        best = c;
+                    }
+                }
+                best
+            }
+            Outline::Circle(c, r) => {
+                let d = c.distance(&p);
+                if d == 0.0 {
+                    Point::new(c.x + r, c.y)
+                } else {
+                    Point::new(c.x + (p.x - c.x) * r / d, c.y + (p.y - c.y) * r / d)
+                }
+            }
+        }
+    }
+
+    /// shortest distance to another outline, 0 if they overlap
+    pub fn distance(&self, o: &Outline) -> f64 {
+        match (*self, *o) {
+            (Outline::Circle(c1, r1), Outline::Circle(c2, r2)) => {
+                (c1.distance(&c2) - r1 - r2).max(0.0)
+            }
+            (Outline::Circle(c, r), Outline::Polygon(ref p))
+            | (Outline::Polygon(ref p), Outline::Circle(c, r)) => {
+                (polygon_point_distance(p, c) - r).max(0.0)
+            }
+            (Outline::Polygon(ref p), Outline::Polygon(ref q)) => {
+                if polygon_contains(p, q[0]) || polygon_contains(q, p[0]) {
+                    return 0.0;
+                }
+                (0..4)
+                    .map(|i| segment_polygon_distance(p[i], p[(i + 1) % 4], q))
+                    .fold(::std::f64::INFINITY, f64::min)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Point, b: Point) {
+        assert!(a.distance(&b) < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    fn square(x: f64, y: f64, s: f64) -> Outline {
+        Outline::Polygon([
+            Point::new(x - s, y - s),
+            Point::new(x + s, y - s),
+            Point::new(x + s, y + s),
+            Point::new(x - s, y + s),
+        ])
+    }
+
+    #[test]
+    fn rotate_is_counter_clockwise_on_screen() {
+        // y points down, so counter-clockwise turns +x into -y
+        let p = Transform::rotate(90.0).apply(Point::new(1.0, 0.0));
+        assert_close(p, Point::new(0.0, -1.0));
+        assert_eq!(Transform::rotate(90.0).rotation().round(), 90.0);
+    }
+
+    #[test]
+    fn mirror_x_flips_x() {
+        let t = Transform::mirror_x();
+        assert_close(t.apply(Point::new(2.0, 3.0)), Point::new(-2.0, 3.0));
+        assert!(t.is_mirrored());
+        assert!(!t.then(&Transform::mirror_x()).is_mirrored());
+    }
+
+    #[test]
+    fn then_applies_self_first() {
+        let t = Transform::translate(1.0, 0.0).then(&Transform::rotate(90.0));
+        assert_close(t.apply(Point::new(0.0, 0.0)), Point::new(0.0, -1.0));
+        let t = Transform::rotate(90.0).then(&Transform::translate(1.0, 0.0));
+        assert_close(t.apply(Point::new(0.0, 0.0)), Point::new(1.0, 0.0));
+        let t = Transform::scale(2.0).then(&Transform::translate(1.0, 1.0));
+        assert_close(t.apply(Point::new(1.0, 1.0)), Point::new(3.0, 3.0));
+        assert_eq!(t.scale_factor(), 2.0);
+    }
+
+    #[test]
+    fn invert_undoes() {
+        let t = Transform::rotate(30.0)
+            .then(&Transform::scale(3.0))
+            .then(&Transform::mirror_x())
+            .then(&Transform::translate(-2.0, 5.0));
+        let p = Point::new(1.5, -0.25);
+        assert_close(t.invert().apply(t.apply(p)), p);
+        assert_close(t.apply(t.invert().apply(p)), p);
+    }
+
+    #[test]
+    fn apply_rotation() {
+        assert_eq!(Transform::rotate(90.0).apply_rotation(45.0).round(), 135.0);
+        assert_eq!(Transform::rotate(-90.0).apply_rotation(45.0).round(), 315.0);
+        // mirroring turns the rotation the other way
+        assert_eq!(Transform::mirror_x().apply_rotation(30.0).round(), 330.0);
+        assert_eq!(normalize_angle(-90.0), 270.0);
+        assert_eq!(normalize_angle(720.0), 0.0);
+    }
+
+    #[test]
+    fn segment_polygon_distance_cases() {
+        let p = [
+            Point::new(0.0, 0.0),
+            Point::new(1.0, 0.0),
+            Point::new(1.0, 1.0),
+            Point::new(0.0, 1.0),
+        ];
+        // beside the square
+        let d = segment_polygon_distance(Point::new(2.0, -1.0), Point::new(2.0, 2.0), &p);
+        assert!((d - 1.0).abs() < 1e-9);
+        // diagonal from a corner
+        let d = segment_polygon_distance(Point::new(2.0, 2.0), Point::new(3.0, 3.0), &p);
+        assert!((d - 2f64.sqrt()).abs() < 1e-9);
+        // an end inside
+        let d = segment_polygon_distance(Point::new(0.5, 0.5), Point::new(3.0, 3.0), &p);
+        assert_eq!(d, 0.0);
+        // crossing without an end inside
+        let d = segment_polygon_distance(Point::new(-1.0, 0.5), Point::new(2.0, 0.5), &p);
+        assert_eq!(d, 0.0);
+    }
+
+    #[test]
+    fn outline_distance() {
+        let a = square(0.0, 0.0, 1.0);
+        assert!((a.distance(&square(3.0, 0.0, 1.0)) - 1.0).abs() < 1e-9);
+        assert_eq!(a.distance(&square(1.5, 0.0, 1.0)), 0.0);
+        // one inside the other
+        assert_eq!(a.distance(&square(0.0, 0.0, 0.25)), 0.0);
+        // a cross: overlapping, but no corner inside the other
+        let wide = Outline::Polygon([
+            Point::new(-2.0, -0.5),
+            Point::new(2.0, -0.5),
+            Point::new(2.0, 0.5),
+            Point::new(-2.0, 0.5),
+        ]);
+        let tall = Outline::Polygon([
+            Point::new(-0.5, -2.0),
+            Point::new(0.5, -2.0),
+            Point::new(0.5, 2.0),
+            Point::new(-0.5, 2.0),
+        ]);
+        assert_eq!(wide.distance(&tall), 0.0);
+
+        let c = Outline::Circle(Point::new(4.0, 0.0), 1.0);
+        assert!((a.distance(&c) - 2.0).abs() < 1e-9);
+        assert!((c.distance(&a) - 2.0).abs() < 1e-9);
+        let c2 = Outline::Circle(Point::new(4.0, 3.0), 1.0);
+        assert!((c.distance(&c2) - 1.0).abs() < 1e-9);
+        assert_eq!(c.distance(&Outline::Circle(Point::new(5.0, 0.0), 1.0)), 0.0);
+        // diagonal to a corner of the square
+        let c3 = Outline::Circle(Point::new(2.0, 2.0), 0.5);
+        assert!((a.distance(&c3) - (2f64.sqrt() - 0.5)).abs() < 1e-9);
+    }
+}
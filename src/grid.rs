@@ -0,0 +1,230 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// The grid in the view. The spacing adapts to the zoom level, so the grid
+// never gets denser than a few pixels, and only the visible part is drawn.
+
+use cairo;
+
+use element::Bound;
+use geometry::Point;
+use layers::Layer;
+use scheme;
+
+/// minor grid lines closer together than this many pixels are left out
+const MIN_SPACING: f64 = 8.0;
+
+const MM_PER_MIL: f64 = 0.0254;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridUnit {
+    Mm,
+    Mil,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridStyle {
+    Dots,
+    Lines,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Grid {
+    pub unit: GridUnit,
+    /// spacing of the minor grid, in unit
+    pub spacing: f64,
+    /// number of minor grid steps in a major one
+    pub major: u32,
+    pub style: GridStyle,
+    /// the grid and the snapping to it start here, in mm
+    pub origin_x: f64,
+    pub origin_y: f64,
+}
+
+impl Default for Grid {
+    fn default() -> Grid {
+        Grid {
+            unit: GridUnit::Mm,
+            spacing: 0.1,
+            major: 10,
+            style: GridStyle::Dots,
+            origin_x: 0.0,
+            origin_y: 0.0,
+        }
+    }
+}
+
+impl Grid {
+    /// spacing of the minor grid in mm
+    pub fn step(&self) -> f64 {
+        match self.unit {
+            GridUnit::Mm => self.spacing,
+            GridUnit::Mil => self.spacing * MM_PER_MIL,
+        }
+    }
+
+    fn major(&self) -> u32 {
+        self.major.max(1)
+    }
+
+    /// minor spacing in mm at a zoom level of pixel mm per pixel; when
+    /// the grid is too dense, every major step becomes a minor one
+    pub fn visible_step(&self, pixel: f64) -> f64 {
+        let factor = if self.major() > 1 {
+            self.major() as f64
+        } else {
+            10.0
+        };
+        // between a micrometer and a meter, whatever is in the settings
+        let mut step = self.step().max(1e-3).min(1e3);
+        if !(pixel > 0.0 && pixel.is_finite()) {
+            return step;
+        }
+        while step / pixel < MIN_SPACING && (step * factor).is_finite() {
+            step *= factor;
+        }
+        step
+    }
+
+    /// the closest point of the visible grid
+    pub fn snap(&self, p: Point, pixel: f64) -> Point {
+        let step = self.visible_step(pixel);
+        Point::new(
+            self.origin_x + ((p.x - self.origin_x) / step).round() * step,
+            self.origin_y + ((p.y - self.origin_y) / step).round() * step,
+        )
+    }
+
+    /// indices of the grid steps from origin within min..max
+    fn range(origin: f64, step: f64, min: f64, max: f64) -> ::std::ops::Range<i64> {
+        let first = ((min - origin) / step).ceil() as i64;
+        let last = ((max - origin) / step).floor() as i64;
+        first..last.saturating_add(1)
+    }
+
+    /// draw the part of the grid that is in the visible bound
+    pub fn draw(&self, cr: &cairo::Context, visible: &Bound, pixel: f64) {
+        let step = self.visible_step(pixel);
+        let major = self.major() as i64;
+        let xs = Grid::range(self.origin_x, step, visible.min_x, visible.max_x);
+        let ys = Grid::range(self.origin_y, step, visible.min_y, visible.max_y);
+        let x = |i: i64| self.origin_x + i as f64 * step;
+        let y = |i: i64| self.origin_y + i as f64 * step;
+        let color = scheme::color(Layer::Grid);
+        match self.style {
+            GridStyle::Dots => {
+                cr.set_line_cap(cairo::enums::LineCap::Round);
+                for &is_major in &[false, true] {
+                    if is_major {
+                        color.set_source(cr);
+                        cr.set_line_width(3.0 * pixel);
+                    } else {
+                        color.with_alpha(0.5).set_source(cr);
+                        cr.set_line_width(2.0 * pixel);
+                    }
+                    for ix in xs.clone() {
+                        for iy in ys.clone() {
+                            if (ix % major == 0 && iy % major == 0) == is_major {
+                                cr.move_to(x(ix), y(iy));
+                                cr.close_path();
+                            }
+                        }
+                    }
+                    cr.stroke();
+                }
+            }
+            GridStyle::Lines => {
+                cr.set_line_width(pixel);
+                for &is_major in &[false, true] {
+                    if is_major {
+                        color.set_source(cr);
+                    } else {
+                        color.with_alpha(0.3).set_source(cr);
+                    }
+                    for ix in xs.clone().filter(|i| (i % major == 0) == is_major) {
+                        cr.move_to(x(ix), visible.min_y);
+                        cr.line_to(x(ix), visible.max_y);
+                    }
+                    for iy in ys.clone().filter(|i| (i % major == 0) == is_major) {
+                        cr.move_to(visible.min_x, y(iy));
+                        cr.line_to(visible.max_x, y(iy));
+                    }
+                    cr.stroke();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(unit: GridUnit, spacing: f64, major: u32) -> Grid {
+        Grid {
+            unit,
+            spacing,
+            major,
+            ..Grid::default()
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(Grid::range(0.0, 1.0, -2.5, 2.5), -2..3);
+        // lines exactly on the edges are in
+        assert_eq!(Grid::range(0.0, 1.0, -2.0, 2.0), -2..3);
+        assert_eq!(Grid::range(0.5, 1.0, 0.0, 2.0), 0..2);
+        assert_eq!(Grid::range(0.0, 0.1, 0.25, 0.25).count(), 0);
+        assert_eq!(Grid::range(0.0, 0.5, 1.0, 1.0), 2..3);
+        // an empty or inverted bound has no lines
+        assert_eq!(Grid::range(0.0, 1.0, 2.0, -2.0).count(), 0);
+        // no overflow for absurd bounds
+        let r = Grid::range(0.0, 1e-3, -1e300, 1e300);
+        assert_eq!((r.start, r.end), (i64::min_value(), i64::max_value()));
+    }
+
+    #[test]
+    fn visible_step() {
+        let g = Grid::default();
+        assert!(close(g.visible_step(0.01), 0.1));
+        assert!(close(g.visible_step(0.1), 1.0));
+        assert!(close(g.visible_step(1.0), 10.0));
+        // without major lines the step grows by 10
+        assert!(close(grid(GridUnit::Mm, 0.5, 1).visible_step(0.1), 5.0));
+        assert!(close(grid(GridUnit::Mil, 50.0, 2).visible_step(0.001), 1.27));
+        assert!(close(grid(GridUnit::Mil, 50.0, 2).visible_step(0.2), 2.54));
+    }
+
+    const PIXELS: [f64; 7] = [
+        0.0,
+        -1.0,
+        1e-300,
+        1e-9,
+        1e300,
+        ::std::f64::INFINITY,
+        ::std::f64::NAN,
+    ];
+
+    #[test]
+    fn visible_step_extremes() {
+        for g in &[
+            Grid::default(),
+            grid(GridUnit::Mm, 0.0, 0),
+            grid(GridUnit::Mm, -1.0, 10),
+            grid(GridUnit::Mm, 1e308, 10),
+            grid(GridUnit::Mil, ::std::f64::NAN, 5),
+        ] {
+            for &pixel in &PIXELS {
+                let step = g.visible_step(pixel);
+                assert!(step > 0.0 && step.is_finite(), "{:?} {}: {}", g, pixel, step);
+            }
+        }
+    }
+}
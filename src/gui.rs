@@ -1,109 +1,370 @@
 // (c) 2018 Joost Yervante Damad <joost@damad.be>
 
 use cairo;
+use gdk;
+use gdk::{EventMask, ModifierType, ScrollDirection};
 use gdk_pixbuf::Pixbuf;
 use gio::MemoryInputStream;
 use glib::Bytes;
 use gtk;
 use gtk::prelude::*;
-use gtk::{AboutDialog, DrawingArea, Menu, MenuBar, MenuItem, Statusbar};
-use gtk::{Label, Notebook, ScrolledWindow, TextBuffer, TextView, Window};
+use gtk::{AboutDialog, ComboBoxText, DrawingArea, Menu, MenuBar, MenuItem, Spinner, Statusbar};
+use gtk::{Frame, Grid, Label, Notebook, ScrolledWindow, SpinButton, TextBuffer, TextView, Window};
+use gtk::{CellRendererText, Expander, ListStore, Paned, TextTag, TextTagTable};
+use gtk::{Button, CheckButton, CheckMenuItem, Scale, SeparatorMenuItem};
+use gtk::{TreePath, TreeView, TreeViewColumn};
 
 use gtk::{FileChooserAction, FileChooserDialog, FileChooserExt, ResponseType};
 
-use std::fs;
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::f64::consts::PI;
+use std::rc::Rc;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use VERSION;
 
+use editor::Editor;
+
 use DrawState;
 
-use element::DrawElement;
-use layers::{Layer, LAYER, LAYER_Z};
+use check::Issue;
+use element::{self, Bound, BoundingBox, Element, Inspect, PythonError};
+use geometry::{Point, Transform};
+use measure::{self, Snap};
+use layers::{Layer, LayerView, FOOTPRINT_LAYERS, LAYER_Z};
+use python::Param;
+use render::{self, ImageOptions};
+use scheme::{self, ColorScheme};
 
 const ICON: &'static str = include_str!("../media/icon.svg");
 
+/// factor one scroll wheel step zooms in or out
+const ZOOM_STEP: f64 = 1.25;
+
+fn area_size(area: &DrawingArea) -> (f64, f64) {
+    (
+        area.get_allocated_width().into(),
+        area.get_allocated_height().into(),
+    )
+}
+
+/// zoomed and panned transform, without the mirroring of the back view
+fn zoom_transform(draw_state: &DrawState, w: f64, h: f64) -> Transform {
+    match draw_state.view {
+        Some(t) => t,
+        None => render::fit_transform(&draw_state.bound, w, h),
+    }
+}
+
+/// current transform from mm to pixels; the back view is mirrored around
+/// the center of the footprint, so it stays in place
+fn view_transform(draw_state: &DrawState, w: f64, h: f64) -> Transform {
+    let zoom = zoom_transform(draw_state, w, h);
+    if draw_state.back {
+        render::back_transform(&draw_state.bound).then(&zoom)
+    } else {
+        zoom
+    }
+}
+
+/// cursor position in mm, snapped
+fn snapped_point(draw_state: &DrawState, area: &DrawingArea, x: f64, y: f64, snap: &Snap) -> Point {
+    let (w, h) = area_size(area);
+    let t = view_transform(draw_state, w, h);
+    let p = t.invert().apply(Point::new(x, y));
+    measure::snap(
+        p,
+        &draw_state.elements,
+        snap,
+        &draw_state.grid,
+        1.0 / t.scale_factor(),
+    )
+}
+
+/// index of the visible element under the cursor
+fn element_under(draw_state: &DrawState, area: &DrawingArea, x: f64, y: f64) -> Option<usize> {
+    let (w, h) = area_size(area);
+    let t = view_transform(draw_state, w, h);
+    let p = t.invert().apply(Point::new(x, y));
+    element::element_at(&draw_state.bounds, p, 4.0 / t.scale_factor(), |i| {
+        draw_state.elements[i].layers().iter().any(|l| {
+            draw_state.layers.get(l).map(|v| v.visible).unwrap_or(true)
+        })
+    })
+}
+
+/// draw a rectangle just around a bound
+fn mark_bound(cr: &cairo::Context, b: &Bound, pixel: f64, alpha: f64) {
+    let margin = 4.0 * pixel;
+    cr.set_source_rgba(1.0, 0.0, 1.0, alpha);
+    cr.set_line_width(2.0 * pixel);
+    cr.rectangle(
+        b.min_x - margin,
+        b.min_y - margin,
+        b.max_x - b.min_x + 2.0 * margin,
+        b.max_y - b.min_y + 2.0 * margin,
+    );
+    cr.stroke();
+}
+
+/// zoom or pan: change the view transform, starting from the fitted
+/// view if there is no zoomed view yet
+fn change_view<F: Fn(Transform) -> Transform>(
+    draw_state: &Arc<Mutex<DrawState>>,
+    area: &DrawingArea,
+    f: F,
+) {
+    let mut draw_state = draw_state.lock().unwrap();
+    if draw_state.elements.is_empty() {
+        return;
+    }
+    let (w, h) = area_size(area);
+    let t = zoom_transform(&draw_state, w, h);
+    draw_state.view = Some(f(t));
+    area.queue_draw();
+}
+
 fn draw_fn(draw_state: Arc<Mutex<DrawState>>, area: &DrawingArea, cr: &cairo::Context) -> Inhibit {
-    let w: f64 = area.get_allocated_width().into();
-    let h: f64 = area.get_allocated_height().into();
-    info!("w: {}, h: {}", w, h);
+    let (w, h) = area_size(area);
 
     // set background
-    LAYER[&Layer::Background].color.set_source(cr);
+    scheme::color(Layer::Background).set_source(cr);
     cr.rectangle(0.0, 0.0, w, h);
     cr.fill();
 
-    // scale x and y
     let draw_state = draw_state.lock().unwrap();
     if draw_state.elements.is_empty() {
         return Inhibit(false);
     }
-    let dw = draw_state.bound.max_x - draw_state.bound.min_x;
-    let dh = draw_state.bound.max_y - draw_state.bound.min_y;
-    info!("dw: {}, dh: {}", dw, dh);
-    let t = w / h;
-    let dt = dw / dh;
-    info!("t: {}, dt: {}", t, dt);
-    let (sw, sh) = if t > dt {
-        // dh is limiting factor
-        cr.scale(w / (w * dh / h), h / dh);
-        info!("scaling to: {}, {}", w * dh / h, dh);
-        (w * dh / h, dh)
-    } else {
-        // dw is limiting factor
-        cr.scale(w / dw, h / (h * dw / w));
-        info!("scaling to: {}, {}", dw, h * dw / w);
-        (dw, h * dw / w)
-    };
+    let t = view_transform(&draw_state, w, h);
+    cr.transform(render::matrix(&t));
+    // one pixel in mm
+    let pixel = 1.0 / t.scale_factor();
+    render::draw_scene(cr, &draw_state, &render::visible_bound(&t, w, h), pixel, true);
+
+    // mark the hovered element and the highlighted area
+    if let Some(b) = draw_state.hover.and_then(|i| draw_state.bounds.get(i)) {
+        mark_bound(cr, b, pixel, 0.4);
+    }
+    if let Some(ref b) = draw_state.highlight {
+        mark_bound(cr, b, pixel, 0.9);
+    }
+
+    // measurement ruler
+    if let Some((a, b)) = draw_state.ruler {
+        if a != b {
+            cr.set_source_rgba(1.0, 1.0, 0.0, 0.9);
+            cr.set_line_width(pixel);
+            cr.move_to(a.x, a.y);
+            cr.line_to(b.x, b.y);
+            cr.stroke();
+            for p in &[a, b] {
+                cr.arc(p.x, p.y, 3.0 * pixel, 0.0, 2.0 * PI);
+                cr.stroke();
+            }
+        }
+    }
+
+    Inhibit(false)
+}
 
-    // translate origin
-    cr.translate(
-        -draw_state.bound.min_x + (sw - dw) / 2.0,
-        -draw_state.bound.min_y + (sh - dh) / 2.0,
+/// change how a layer is shown and redraw
+fn change_layer_view<F: Fn(&mut LayerView)>(
+    draw_state: &Arc<Mutex<DrawState>>,
+    area: &DrawingArea,
+    layer: Layer,
+    f: F,
+) {
+    let mut draw_state = draw_state.lock().unwrap();
+    f(draw_state.layers.entry(layer).or_insert_with(LayerView::default));
+    area.queue_draw();
+}
+
+/// a checkbox and opacity slider for every footprint layer in the view;
+/// solo shows only that layer
+fn make_layer_panel(draw_state: &Arc<Mutex<DrawState>>, view: &DrawingArea) -> Frame {
+    let grid = Grid::new();
+    grid.set_row_spacing(5);
+    grid.set_column_spacing(10);
+    grid.set_border_width(5);
+    let views = draw_state.lock().unwrap().layers.clone();
+    let layers = LAYER_Z
+        .iter()
+        .map(|(_, layer)| *layer)
+        .filter(|layer| FOOTPRINT_LAYERS.contains(layer))
+        .collect::<Vec<Layer>>();
+    let checks = Rc::new(
+        layers
+            .iter()
+            .map(|layer| (*layer, CheckButton::new_with_label(&layer.to_string())))
+            .collect::<Vec<(Layer, CheckButton)>>(),
     );
+    for (row, &(layer, ref check)) in checks.iter().enumerate() {
+        let layer_view = views.get(&layer).cloned().unwrap_or_default();
+        check.set_active(layer_view.visible);
+        let draw_state2 = draw_state.clone();
+        let view2 = view.clone();
+        check.connect_toggled(move |check| {
+            let visible = check.get_active();
+            change_layer_view(&draw_state2, &view2, layer, |v| v.visible = visible);
+        });
 
-    // draw axes
-    LAYER[&Layer::Axes].color.set_source(cr);
-    cr.move_to(-dw * 2.0, 0.0);
-    cr.set_line_width(0.01);
-    cr.line_to(dw * 2.0, 0.0);
-    cr.stroke();
-    cr.move_to(0.0, -dh * 2.0);
-    cr.line_to(0.0, dh * 2.0);
-    cr.stroke();
+        let opacity = Scale::new_with_range(gtk::Orientation::Horizontal, 0.0, 1.0, 0.05);
+        opacity.set_draw_value(false);
+        opacity.set_size_request(100, -1);
+        opacity.set_value(layer_view.opacity);
+        let draw_state2 = draw_state.clone();
+        let view2 = view.clone();
+        opacity.connect_value_changed(move |scale| {
+            let opacity = scale.get_value();
+            change_layer_view(&draw_state2, &view2, layer, |v| v.opacity = opacity);
+        });
 
-    // draw unit dots
-    LAYER[&Layer::Grid].color.set_source(cr);
-    cr.set_line_cap(cairo::enums::LineCap::Round);
-    cr.set_line_width(0.01);
-    for ix in -((dw * 2.0) as i32)..((dw * 2.0) as i32) {
-        for iy in -((dh * 2.0) as i32)..((dh * 2.0) as i32) {
-            cr.move_to(ix as f64, iy as f64);
-            cr.close_path();
-        }
+        let solo = Button::new_with_label("solo");
+        let checks2 = checks.clone();
+        solo.connect_clicked(move |_| {
+            for &(l, ref check) in checks2.iter() {
+                check.set_active(l == layer);
+            }
+        });
+
+        grid.attach(check, 0, row as i32, 1, 1);
+        grid.attach(&opacity, 1, row as i32, 1, 1);
+        grid.attach(&solo, 2, row as i32, 1, 1);
     }
-    cr.stroke();
+    let all = Button::new_with_label("show all");
+    let checks2 = checks.clone();
+    all.connect_clicked(move |_| {
+        for &(_, ref check) in checks2.iter() {
+            check.set_active(true);
+        }
+    });
+    grid.attach(&all, 0, layers.len() as i32, 3, 1);
+
+    let frame = Frame::new(Some("layers"));
+    frame.add(&grid);
+    frame
+}
+
+/// list of all elements of the footprint with the properties of the
+/// selected one; the selected element is marked in the view
+fn make_element_panel(
+    draw_state: &Arc<Mutex<DrawState>>,
+    view: &DrawingArea,
+) -> (gtk::Box, TreeView, ListStore) {
+    // label, index in DrawState.elements
+    let store = ListStore::new(&[gtk::Type::String, gtk::Type::U32]);
+    let list = TreeView::new_with_model(&store);
+    add_text_column(&list, "element", 0);
+    let scrolled = ScrolledWindow::new(None, None);
+    scrolled.set_min_content_height(150);
+    scrolled.add(&list);
+    let elements_frame = Frame::new(Some("elements"));
+    elements_frame.add(&scrolled);
 
-    // draw elements, layer by layer
-    for (_z, layer) in LAYER_Z.iter() {
-        for e in &draw_state.elements {
-            e.draw_element(cr, *layer);
+    let properties_store = ListStore::new(&[gtk::Type::String, gtk::Type::String]);
+    let properties = TreeView::new_with_model(&properties_store);
+    add_text_column(&properties, "property", 0);
+    add_text_column(&properties, "value", 1);
+    let properties_frame = Frame::new(Some("properties"));
+    properties_frame.add(&properties);
+
+    let draw_state = draw_state.clone();
+    let view = view.clone();
+    list.get_selection().connect_changed(move |selection| {
+        properties_store.clear();
+        let index = selection
+            .get_selected()
+            .and_then(|(model, iter)| model.get_value(&iter, 1).get::<u32>());
+        // locked while a new footprint is shown, see make_issue_list
+        if let Ok(mut draw_state) = draw_state.try_lock() {
+            let element = index.and_then(|i| draw_state.elements.get(i as usize).cloned());
+            draw_state.highlight = element.as_ref().map(|e| e.bounding_box());
+            if let Some(e) = element {
+                for (name, value) in e.properties() {
+                    properties_store.insert_with_values(None, &[0, 1], &[&name, &value]);
+                }
+            }
         }
+        view.queue_draw();
+    });
+
+    let panel = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    panel.pack_start(&elements_frame, true, true, 0);
+    panel.pack_start(&properties_frame, false, false, 0);
+    (panel, list, store)
+}
+
+/// short description of an element, for the tooltip in the view
+fn element_tooltip(e: &Element) -> String {
+    let mut lines = vec![e.label()];
+    for (name, value) in e.properties() {
+        lines.push(format!("{}: {}", name, value));
     }
+    lines.join("\n")
+}
 
-    Inhibit(false)
+fn add_text_column(tree: &TreeView, title: &str, column: i32) {
+    let c = TreeViewColumn::new();
+    c.set_title(title);
+    let cell = CellRendererText::new();
+    c.pack_start(&cell, true);
+    c.add_attribute(&cell, "text", column);
+    tree.append_column(&c);
+}
+
+/// highlight one line (counting from 1) of a text buffer with a tag;
+/// the tag is removed from everywhere else
+fn highlight_line(buffer: &TextBuffer, tag: &TextTag, line: usize) {
+    let (start, end) = buffer.get_bounds();
+    buffer.remove_tag(tag, &start, &end);
+    let start = buffer.get_iter_at_line(line as i32 - 1);
+    let mut end = start.clone();
+    end.forward_line();
+    buffer.apply_tag(tag, &start, &end);
+}
+
+/// put the cursor at the start of a line and scroll it into view
+fn scroll_to_line(view: &TextView, line: usize) {
+    if let Some(buffer) = view.get_buffer() {
+        buffer.place_cursor(&buffer.get_iter_at_line(line as i32 - 1));
+        if let Some(mark) = buffer.get_insert() {
+            view.scroll_to_mark(&mark, 0.0, true, 0.0, 0.3);
+        }
+    }
 }
 
 pub struct GuiData {
     window: Window,
-    statusbar: Statusbar,
+    spinner: Spinner,
+    input: TextView,
     input_buffer: TextBuffer,
+    editor: Editor,
+    /// the script being shown, to tell its traceback frames apart
+    script: PathBuf,
+    error_box: gtk::Box,
+    error_label: Label,
+    /// traceback frames: location, line, in script
+    error_store: ListStore,
+    error_tag: TextTag,
+    frame_tag: TextTag,
     klc_buffer: TextBuffer,
+    klc_store: ListStore,
+    drc_store: ListStore,
+    elements_store: ListStore,
     notebook: Notebook,
+    variants: ComboBoxText,
+    variants_box: gtk::Box,
+    params_frame: Frame,
+    params_grid: Grid,
+    params: RefCell<Vec<(Param, SpinButton)>>,
     exit: Arc<AtomicBool>,
     save: Arc<AtomicBool>,
+    save_image: Arc<AtomicBool>,
+    variant_changed: Arc<AtomicBool>,
+    params_changed: Arc<AtomicBool>,
 }
 
 impl GuiData {
@@ -127,6 +388,107 @@ impl GuiData {
         self.save.compare_and_swap(true, false, Ordering::SeqCst)
     }
 
+    pub fn want_save_image(&self) -> bool {
+        self.save_image.compare_and_swap(true, false, Ordering::SeqCst)
+    }
+
+    pub fn want_variant(&self) -> bool {
+        self.variant_changed
+            .compare_and_swap(true, false, Ordering::SeqCst)
+    }
+
+    pub fn selected_variant(&self) -> usize {
+        self.variants.get_active().max(0) as usize
+    }
+
+    /// fill the variant dropdown, keeping the current selection if possible;
+    /// the dropdown is only shown for scripts with more than one variant
+    pub fn set_variants(&self, labels: &[String]) {
+        let selected = self.selected_variant();
+        self.variants.remove_all();
+        for label in labels {
+            self.variants.append_text(label);
+        }
+        if selected < labels.len() {
+            self.variants.set_active(selected as i32);
+        } else {
+            self.variants.set_active(0);
+        }
+        self.variant_changed.store(false, Ordering::SeqCst);
+        if labels.len() > 1 {
+            self.variants_box.show();
+        } else {
+            self.variants_box.hide();
+        }
+    }
+
+    pub fn want_params(&self) -> bool {
+        self.params_changed
+            .compare_and_swap(true, false, Ordering::SeqCst)
+    }
+
+    /// current values of all script parameters
+    pub fn param_overrides(&self) -> Vec<(String, f64)> {
+        self.params
+            .borrow()
+            .iter()
+            .map(|(p, spin)| (p.name.clone(), spin.get_value()))
+            .collect()
+    }
+
+    /// show a spin button for every script parameter; values the user
+    /// already tuned are kept as long as the script declares the same
+    /// parameters with the same defaults
+    pub fn set_params(&self, params: &[Param]) {
+        let same = {
+            let old = self.params.borrow();
+            old.len() == params.len()
+                && old.iter().zip(params.iter()).all(|((o, _), n)| {
+                    o.name == n.name && o.value == n.value && o.min == n.min
+                        && o.max == n.max && o.integer == n.integer
+                })
+        };
+        if same {
+            return;
+        }
+        // if the evaluation used a value that is different from the new
+        // default, evaluate again with the defaults
+        let overrides = self.param_overrides();
+        let stale = params.iter().any(|n| {
+            overrides
+                .iter()
+                .any(|(name, value)| *name == n.name && *value != n.value)
+        });
+        for child in self.params_grid.get_children() {
+            self.params_grid.remove(&child);
+        }
+        let mut new_params = vec![];
+        for (row, param) in params.iter().enumerate() {
+            let label = Label::new(Some(param.name.as_str()));
+            label.set_halign(gtk::Align::Start);
+            let step = if param.integer { 1.0 } else { 0.01 };
+            let spin = SpinButton::new_with_range(param.min, param.max, step);
+            spin.set_digits(if param.integer { 0 } else { 3 });
+            spin.set_value(param.value);
+            let params_changed = self.params_changed.clone();
+            spin.connect_value_changed(move |_| {
+                params_changed.store(true, Ordering::SeqCst);
+            });
+            label.show();
+            spin.show();
+            self.params_grid.attach(&label, 0, row as i32, 1, 1);
+            self.params_grid.attach(&spin, 1, row as i32, 1, 1);
+            new_params.push((param.clone(), spin));
+        }
+        *self.params.borrow_mut() = new_params;
+        self.params_changed.store(stale, Ordering::SeqCst);
+        if params.is_empty() {
+            self.params_frame.hide();
+        } else {
+            self.params_frame.show();
+        }
+    }
+
     pub fn show_drawing_page(&self) {
         self.notebook.set_current_page(Some(0));
     }
@@ -135,20 +497,189 @@ impl GuiData {
         self.notebook.set_current_page(Some(1));
     }
     pub fn set_status(&self, status: &str) {
-        self.statusbar.pop(0);
-        self.statusbar.push(0, status);
+        self.editor.set_status(status);
+    }
+
+    /// show a spinner while a footprint script is being evaluated
+    pub fn set_busy(&self, busy: bool) {
+        if busy {
+            self.set_status("Evaluating...");
+            self.spinner.start();
+        } else {
+            self.set_status("");
+            self.spinner.stop();
+        }
+    }
+
+    /// the script changed on disk, or was saved from the editor
+    pub fn file_changed(&self, data: &str) {
+        self.editor.file_changed(data, &self.window);
+    }
+
+    fn is_script(&self, filename: &str) -> bool {
+        Path::new(filename)
+            .canonicalize()
+            .map(|p| p == self.script)
+            .unwrap_or(false)
+    }
+
+    /// show a script error in the pane below the source, and highlight
+    /// the line of the innermost frame in the script
+    pub fn show_python_error(&self, e: &PythonError) {
+        self.error_label.set_text(e.exception.trim_right());
+        self.error_store.clear();
+        let mut failing = None;
+        for frame in &e.frames {
+            let in_script = self.is_script(&frame.filename);
+            if in_script {
+                failing = Some(frame.line);
+            }
+            let location = format!("{}:{} in {}", frame.filename, frame.line, frame.function);
+            self.error_store.insert_with_values(
+                None,
+                &[0, 1, 2],
+                &[&location, &(frame.line as u32), &in_script],
+            );
+        }
+        let (start, end) = self.input_buffer.get_bounds();
+        self.input_buffer.remove_tag(&self.frame_tag, &start, &end);
+        match failing {
+            Some(line) => {
+                highlight_line(&self.input_buffer, &self.error_tag, line);
+                scroll_to_line(&self.input, line);
+            }
+            None => self.input_buffer.remove_tag(&self.error_tag, &start, &end),
+        }
+        self.error_box.show();
     }
 
-    pub fn set_input_text(&self, txt: &str) {
-        self.input_buffer.set_text(txt);
+    /// show a problem that has no traceback in the error pane
+    pub fn show_error_text(&self, text: &str) {
+        self.clear_python_error();
+        self.error_label.set_text(text);
+        self.error_box.show();
+    }
+
+    pub fn clear_python_error(&self) {
+        self.error_box.hide();
+        self.error_store.clear();
+        let (start, end) = self.input_buffer.get_bounds();
+        self.input_buffer.remove_tag(&self.error_tag, &start, &end);
+        self.input_buffer.remove_tag(&self.frame_tag, &start, &end);
     }
 
     pub fn set_klc_text(&self, txt: &str) {
         self.klc_buffer.set_text(txt);
     }
+
+    pub fn set_klc_issues(&self, issues: &[Issue]) {
+        fill_issue_list(&self.klc_store, issues);
+    }
+
+    pub fn set_drc_issues(&self, issues: &[Issue]) {
+        fill_issue_list(&self.drc_store, issues);
+    }
+
+    pub fn set_elements(&self, elements: &[Element]) {
+        self.elements_store.clear();
+        for (i, e) in elements.iter().enumerate() {
+            self.elements_store
+                .insert_with_values(None, &[0, 1], &[&e.label(), &(i as u32)]);
+        }
+    }
 }
 
-pub fn make_gui(filename: &str, draw_state: Arc<Mutex<DrawState>>) -> GuiData {
+/// list of check issues; selecting an issue marks its element in the view
+/// and activating it shows the view
+fn make_issue_list(
+    draw_state: &Arc<Mutex<DrawState>>,
+    view: &DrawingArea,
+    notebook: &Notebook,
+) -> (ScrolledWindow, ListStore) {
+    // severity, rule, message, element, has bound, bound
+    let store = ListStore::new(&[
+        gtk::Type::String,
+        gtk::Type::String,
+        gtk::Type::String,
+        gtk::Type::String,
+        gtk::Type::Bool,
+        gtk::Type::F64,
+        gtk::Type::F64,
+        gtk::Type::F64,
+        gtk::Type::F64,
+    ]);
+    let list = TreeView::new_with_model(&store);
+    for (i, title) in ["severity", "rule", "message", "element"].iter().enumerate() {
+        add_text_column(&list, title, i as i32);
+    }
+    let scrolled = ScrolledWindow::new(None, None);
+    scrolled.add(&list);
+
+    let draw_state = draw_state.clone();
+    let view = view.clone();
+    list.get_selection().connect_changed(move |selection| {
+        let highlight = selection.get_selected().and_then(|(model, iter)| {
+            if !model.get_value(&iter, 4).get::<bool>().unwrap_or(false) {
+                return None;
+            }
+            let v = |column| model.get_value(&iter, column).get::<f64>().unwrap_or(0.0);
+            Some(Bound {
+                min_x: v(5),
+                min_y: v(6),
+                max_x: v(7),
+                max_y: v(8),
+            })
+        });
+        // the list is also cleared while a new footprint is shown, with
+        // the draw state locked; that resets the highlight anyway
+        if let Ok(mut draw_state) = draw_state.try_lock() {
+            draw_state.highlight = highlight;
+        }
+        view.queue_draw();
+    });
+    let notebook = notebook.clone();
+    list.connect_row_activated(move |_, _, _| {
+        notebook.set_current_page(Some(1));
+    });
+    (scrolled, store)
+}
+
+fn fill_issue_list(store: &ListStore, issues: &[Issue]) {
+    store.clear();
+    for issue in issues {
+        let element = issue
+            .element
+            .as_ref()
+            .map(|e| e.to_string())
+            .unwrap_or_default();
+        let b = issue
+            .element
+            .as_ref()
+            .map(|e| e.bound.clone())
+            .unwrap_or_default();
+        store.insert_with_values(
+            None,
+            &[0, 1, 2, 3, 4, 5, 6, 7, 8],
+            &[
+                &issue.severity.to_string(),
+                &issue.rule,
+                &issue.message,
+                &element,
+                &issue.element.is_some(),
+                &b.min_x,
+                &b.min_y,
+                &b.max_x,
+                &b.max_y,
+            ],
+        );
+    }
+}
+
+pub fn make_gui(
+    filename: &str,
+    draw_state: Arc<Mutex<DrawState>>,
+    schemes: Vec<ColorScheme>,
+) -> GuiData {
     let window = gtk::Window::new(gtk::WindowType::Toplevel);
 
     window.set_title(&format!("madparts (rustic edition) {}", VERSION));
@@ -163,11 +694,45 @@ pub fn make_gui(filename: &str, draw_state: Arc<Mutex<DrawState>>) -> GuiData {
     let menu = Menu::new();
     let export = MenuItem::new_with_label("Export");
     menu.append(&export);
+    let export_image = MenuItem::new_with_label("Export image");
+    menu.append(&export_image);
     let quit = MenuItem::new_with_label("Quit");
     menu.append(&quit);
     let file = MenuItem::new_with_label("File");
     file.set_submenu(Some(&menu));
 
+    let menu = Menu::new();
+    let fit = MenuItem::new_with_label("Fit (Home)");
+    menu.append(&fit);
+    let back = CheckMenuItem::new_with_label("View from the back");
+    menu.append(&back);
+    let scheme_menu = Menu::new();
+    let scheme_items = Rc::new(
+        schemes
+            .iter()
+            .map(|s| {
+                let item = CheckMenuItem::new_with_label(&s.name);
+                item.set_draw_as_radio(true);
+                item.set_active(s.name == scheme::current_name());
+                scheme_menu.append(&item);
+                item
+            })
+            .collect::<Vec<CheckMenuItem>>(),
+    );
+    let scheme_item = MenuItem::new_with_label("Color scheme");
+    scheme_item.set_submenu(Some(&scheme_menu));
+    menu.append(&scheme_item);
+    menu.append(&SeparatorMenuItem::new());
+    let snap_grid = CheckMenuItem::new_with_label("Snap to grid");
+    let snap_centers = CheckMenuItem::new_with_label("Snap to pad centers");
+    let snap_edges = CheckMenuItem::new_with_label("Snap to pad edges");
+    for item in &[&snap_grid, &snap_centers, &snap_edges] {
+        item.set_active(true);
+        menu.append(*item);
+    }
+    let view_menu = MenuItem::new_with_label("View");
+    view_menu.set_submenu(Some(&menu));
+
     let menu = Menu::new();
     let about_me = MenuItem::new_with_label("About");
     menu.append(&about_me);
@@ -175,6 +740,7 @@ pub fn make_gui(filename: &str, draw_state: Arc<Mutex<DrawState>>) -> GuiData {
     help.set_submenu(Some(&menu));
 
     menu_bar.append(&file);
+    menu_bar.append(&view_menu);
     menu_bar.append(&help);
 
     let about = {
@@ -200,33 +766,313 @@ pub fn make_gui(filename: &str, draw_state: Arc<Mutex<DrawState>>) -> GuiData {
 
     v_box.pack_start(&menu_bar, false, false, 0);
 
+    // only shown for scripts with variants, see set_variants
+    let variants_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    let variants_label = Label::new(Some("variant:"));
+    variants_label.show();
+    variants_box.pack_start(&variants_label, false, false, 0);
+    let variants = ComboBoxText::new();
+    variants.show();
+    variants_box.pack_start(&variants, true, true, 0);
+    variants_box.set_no_show_all(true);
+    v_box.pack_start(&variants_box, false, false, 0);
+
+    let h_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    v_box.pack_start(&h_box, true, true, 0);
+
     let notebook = Notebook::new();
 
-    v_box.pack_start(&notebook, true, true, 0);
+    h_box.pack_start(&notebook, true, true, 0);
+
+    // only shown for scripts with PARAMS, see set_params
+    let params_frame = Frame::new(Some("parameters"));
+    let params_grid = Grid::new();
+    params_grid.set_row_spacing(5);
+    params_grid.set_column_spacing(10);
+    params_grid.set_border_width(5);
+    params_grid.show();
+    params_frame.add(&params_grid);
+    params_frame.set_no_show_all(true);
+    h_box.pack_start(&params_frame, false, false, 0);
+
+    let error_tag = TextTag::new(Some("error"));
+    error_tag.set_property_paragraph_background(Some("#f4c0c0"));
+    let frame_tag = TextTag::new(Some("frame"));
+    frame_tag.set_property_paragraph_background(Some("#f4e4a0"));
+    let tags = TextTagTable::new();
+    tags.add(&error_tag);
+    tags.add(&frame_tag);
+    let statusbar = Statusbar::new();
+    statusbar.push(0, "Ready.");
+    let editor = Editor::new(Path::new(filename), &tags, &statusbar);
+    let input = editor.view.clone();
+    let input_buffer = editor.buffer.clone();
+
+    // only shown when the script failed, see show_python_error
+    let error_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    let error_label = Label::new(None);
+    error_label.set_halign(gtk::Align::Start);
+    error_label.set_selectable(true);
+    error_label.show();
+    error_box.pack_start(&error_label, false, false, 0);
+    let error_store = ListStore::new(&[gtk::Type::String, gtk::Type::U32, gtk::Type::Bool]);
+    let error_frames = TreeView::new_with_model(&error_store);
+    add_text_column(&error_frames, "traceback", 0);
+    error_frames.show_all();
+    let scrolled_frames = ScrolledWindow::new(None, None);
+    scrolled_frames.add(&error_frames);
+    scrolled_frames.show();
+    error_box.pack_start(&scrolled_frames, true, true, 0);
+    error_box.set_no_show_all(true);
+
+    // clicking a frame in the script scrolls to its line
+    let input2 = input.clone();
+    let input_buffer2 = input_buffer.clone();
+    let frame_tag2 = frame_tag.clone();
+    error_frames.get_selection().connect_changed(move |selection| {
+        if let Some((model, iter)) = selection.get_selected() {
+            let line = model.get_value(&iter, 1).get::<u32>().unwrap_or(0) as usize;
+            let in_script = model.get_value(&iter, 2).get::<bool>().unwrap_or(false);
+            if in_script && line > 0 {
+                highlight_line(&input_buffer2, &frame_tag2, line);
+                scroll_to_line(&input2, line);
+            }
+        }
+    });
+
+    // the highlighted lines are of the evaluated version of the script
+    let error_tag2 = error_tag.clone();
+    let frame_tag2 = frame_tag.clone();
+    input_buffer.connect_changed(move |buffer| {
+        let (start, end) = buffer.get_bounds();
+        buffer.remove_tag(&error_tag2, &start, &end);
+        buffer.remove_tag(&frame_tag2, &start, &end);
+    });
 
-    let input_buffer = TextBuffer::new(None);
-    let data = fs::read_to_string(&filename).unwrap();
-    input_buffer.set_text(&data);
-    let input = TextView::new_with_buffer(&input_buffer);
-    input.set_editable(false);
-    let scrolled_input = ScrolledWindow::new(None, None);
-    scrolled_input.add(&input);
-    notebook.append_page(&scrolled_input, Some(&Label::new(Some("input"))));
+    let input_paned = Paned::new(gtk::Orientation::Vertical);
+    input_paned.pack1(&editor.widget, true, false);
+    input_paned.pack2(&error_box, false, false);
+    let input_label = Label::new(Some("input"));
+    let input_label2 = input_label.clone();
+    input_buffer.connect_modified_changed(move |buffer| {
+        input_label2.set_text(if buffer.get_modified() {
+            "input *"
+        } else {
+            "input"
+        });
+    });
+    notebook.append_page(&input_paned, Some(&input_label));
 
     let view = DrawingArea::new();
-    notebook.append_page(&view, Some(&Label::new(Some("view"))));
+    let view_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    view_box.pack_start(&view, true, true, 0);
+    let (element_panel, elements_list, elements_store) = make_element_panel(&draw_state, &view);
+    let side_panel = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    side_panel.pack_start(&make_layer_panel(&draw_state, &view), false, false, 0);
+    side_panel.pack_start(&element_panel, true, true, 0);
+    view_box.pack_start(&side_panel, false, false, 0);
+    notebook.append_page(&view_box, Some(&Label::new(Some("view"))));
+
+    let draw_state2 = draw_state.clone();
+    view.connect_draw(move |a, c| draw_fn(draw_state2.clone(), a, c));
+
+    // scroll wheel zooms around the cursor, middle drag pans
+    view.add_events(
+        (EventMask::SCROLL_MASK | EventMask::BUTTON_PRESS_MASK | EventMask::BUTTON_RELEASE_MASK
+            | EventMask::POINTER_MOTION_MASK | EventMask::LEAVE_NOTIFY_MASK)
+            .bits() as i32,
+    );
+    let draw_state2 = draw_state.clone();
+    view.connect_scroll_event(move |area, event| {
+        let factor = match event.get_direction() {
+            ScrollDirection::Up => ZOOM_STEP,
+            ScrollDirection::Down => 1.0 / ZOOM_STEP,
+            _ => return Inhibit(false),
+        };
+        let (x, y) = event.get_position();
+        change_view(&draw_state2, area, |t| {
+            t.then(&Transform::translate(-x, -y))
+                .then(&Transform::scale(factor))
+                .then(&Transform::translate(x, y))
+        });
+        Inhibit(true)
+    });
+    // left drag measures, the cursor position is shown in the statusbar
+    let coords = Label::new(None);
+    let current_snap = move || Snap {
+        grid: snap_grid.get_active(),
+        pad_centers: snap_centers.get_active(),
+        pad_edges: snap_edges.get_active(),
+    };
+    let drag: Rc<Cell<Option<(f64, f64)>>> = Rc::new(Cell::new(None));
+    let drag2 = drag.clone();
+    let draw_state2 = draw_state.clone();
+    let current_snap2 = current_snap.clone();
+    view.connect_button_press_event(move |area, event| {
+        match event.get_button() {
+            1 => {
+                let mut draw_state = draw_state2.lock().unwrap();
+                if !draw_state.elements.is_empty() {
+                    let (x, y) = event.get_position();
+                    let p = snapped_point(&draw_state, area, x, y, &current_snap2());
+                    draw_state.ruler = Some((p, p));
+                    area.queue_draw();
+                }
+            }
+            2 => drag2.set(Some(event.get_position())),
+            _ => (),
+        }
+        Inhibit(false)
+    });
+    let drag2 = drag.clone();
+    let draw_state2 = draw_state.clone();
+    view.connect_button_release_event(move |area, event| {
+        match event.get_button() {
+            1 => {
+                // a click without dragging removes the ruler and selects
+                // the element under the cursor
+                let clicked = {
+                    let mut draw_state = draw_state2.lock().unwrap();
+                    match draw_state.ruler {
+                        Some((a, b)) if a == b => {
+                            draw_state.ruler = None;
+                            area.queue_draw();
+                            let (x, y) = event.get_position();
+                            Some(element_under(&draw_state, area, x, y))
+                        }
+                        _ => None,
+                    }
+                };
+                // the selection handler locks the draw state itself
+                match clicked {
+                    Some(Some(i)) => {
+                        let path = TreePath::new_from_indices(&[i as i32]);
+                        elements_list.get_selection().select_path(&path);
+                        let column: Option<&TreeViewColumn> = None;
+                        elements_list.scroll_to_cell(Some(&path), column, false, 0.0, 0.0);
+                    }
+                    Some(None) => elements_list.get_selection().unselect_all(),
+                    None => (),
+                }
+            }
+            2 => drag2.set(None),
+            _ => (),
+        }
+        Inhibit(false)
+    });
+    let draw_state2 = draw_state.clone();
+    let coords2 = coords.clone();
+    view.connect_motion_notify_event(move |area, event| {
+        let (x, y) = event.get_position();
+        if let Some((x0, y0)) = drag.get() {
+            change_view(&draw_state2, area, |t| {
+                t.then(&Transform::translate(x - x0, y - y0))
+            });
+            drag.set(Some((x, y)));
+        }
+        let mut draw_state = draw_state2.lock().unwrap();
+        if draw_state.elements.is_empty() {
+            return Inhibit(false);
+        }
+        let hovered = element_under(&draw_state, area, x, y);
+        if hovered != draw_state.hover {
+            draw_state.hover = hovered;
+            area.queue_draw();
+        }
+        let tooltip = hovered.map(|i| element_tooltip(&draw_state.elements[i]));
+        area.set_tooltip_text(tooltip.as_ref().map(|t| t.as_str()));
+        let p = snapped_point(&draw_state, area, x, y, &current_snap());
+        if event.get_state().contains(ModifierType::BUTTON1_MASK) {
+            if let Some((a, _)) = draw_state.ruler {
+                draw_state.ruler = Some((a, p));
+                area.queue_draw();
+            }
+        }
+        let mut text = format!("x {:.3} y {:.3} mm", p.x, p.y);
+        if let Some((a, b)) = draw_state.ruler {
+            if a != b {
+                text.push_str("  |  ");
+                text.push_str(&measure::describe(a, b));
+            }
+        }
+        coords2.set_text(&text);
+        Inhibit(false)
+    });
 
-    view.connect_draw(move |a, c| draw_fn(draw_state.clone(), a, c));
+    let draw_state2 = draw_state.clone();
+    view.connect_leave_notify_event(move |area, _| {
+        let mut draw_state = draw_state2.lock().unwrap();
+        if draw_state.hover.take().is_some() {
+            area.queue_draw();
+        }
+        Inhibit(false)
+    });
+
+    // fit the footprint in the view again
+    let draw_state2 = draw_state.clone();
+    let view2 = view.clone();
+    let fit_view = move || {
+        draw_state2.lock().unwrap().view = None;
+        view2.queue_draw();
+    };
+    let fit_view2 = fit_view.clone();
+    fit.connect_activate(move |_| fit_view2());
+    // the scheme items behave as radio items
+    for (scheme, item) in schemes.into_iter().zip(scheme_items.iter()) {
+        let scheme_items = scheme_items.clone();
+        let view2 = view.clone();
+        item.connect_toggled(move |item| {
+            if item.get_active() {
+                scheme::set_scheme(&scheme);
+                for other in scheme_items.iter().filter(|i| *i != item) {
+                    other.set_active(false);
+                }
+                view2.queue_draw();
+            } else if scheme::current_name() == scheme.name {
+                item.set_active(true);
+            }
+        });
+    }
+    let draw_state2 = draw_state.clone();
+    let view2 = view.clone();
+    back.connect_toggled(move |item| {
+        draw_state2.lock().unwrap().back = item.get_active();
+        view2.queue_draw();
+    });
+    let notebook2 = notebook.clone();
+    window.connect_key_press_event(move |_, event| {
+        // only when the view is shown, Home is for text otherwise
+        if event.get_keyval() == gdk::enums::key::Home && notebook2.get_current_page() == Some(1)
+        {
+            fit_view();
+            Inhibit(true)
+        } else {
+            Inhibit(false)
+        }
+    });
+
+    let (scrolled_klc_list, klc_store) = make_issue_list(&draw_state, &view, &notebook);
 
     let klc_buffer = TextBuffer::new(None);
     let klc = TextView::new_with_buffer(&klc_buffer);
     klc.set_editable(false);
     let scrolled_klc = ScrolledWindow::new(None, None);
+    scrolled_klc.set_min_content_height(150);
     scrolled_klc.add(&klc);
-    notebook.append_page(&scrolled_klc, Some(&Label::new(Some("KLC"))));
+    let klc_expander = Expander::new(Some("check_kicad_mod.py output"));
+    klc_expander.add(&scrolled_klc);
 
-    let statusbar = Statusbar::new();
-    statusbar.push(0, "Ready.");
+    let klc_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    klc_box.pack_start(&scrolled_klc_list, true, true, 0);
+    klc_box.pack_start(&klc_expander, false, false, 0);
+    notebook.append_page(&klc_box, Some(&Label::new(Some("KLC"))));
+
+    let (drc_list, drc_store) = make_issue_list(&draw_state, &view, &notebook);
+    notebook.append_page(&drc_list, Some(&Label::new(Some("DRC"))));
+
+    let spinner = Spinner::new();
+    statusbar.pack_end(&spinner, false, false, 0);
+    statusbar.pack_end(&coords, false, false, 0);
     v_box.pack_start(&statusbar, false, false, 0);
 
     window.add(&v_box);
@@ -234,13 +1080,21 @@ pub fn make_gui(filename: &str, draw_state: Arc<Mutex<DrawState>>) -> GuiData {
     // exit handling
     let exit = Arc::new(AtomicBool::new(false));
     let exit2 = exit.clone();
-    window.connect_delete_event(move |_, _| {
+    let editor2 = editor.clone();
+    window.connect_delete_event(move |window, _| {
+        if !editor2.confirm_close(window) {
+            return Inhibit(true);
+        }
         exit2.store(true, Ordering::SeqCst);
         Inhibit(false)
     });
     let exit2 = exit.clone();
+    let editor2 = editor.clone();
+    let window2 = window.clone();
     quit.connect_activate(move |_| {
-        exit2.store(true, Ordering::SeqCst);
+        if editor2.confirm_close(&window2) {
+            exit2.store(true, Ordering::SeqCst);
+        }
     });
 
     // save handling
@@ -249,15 +1103,48 @@ pub fn make_gui(filename: &str, draw_state: Arc<Mutex<DrawState>>) -> GuiData {
     export.connect_activate(move |_| {
         save2.store(true, Ordering::SeqCst);
     });
+    let save_image = Arc::new(AtomicBool::new(false));
+    let save_image2 = save_image.clone();
+    export_image.connect_activate(move |_| {
+        save_image2.store(true, Ordering::SeqCst);
+    });
+
+    // variant selection handling
+    let variant_changed = Arc::new(AtomicBool::new(false));
+    let variant_changed2 = variant_changed.clone();
+    variants.connect_changed(move |_| {
+        variant_changed2.store(true, Ordering::SeqCst);
+    });
+
+    let params_changed = Arc::new(AtomicBool::new(false));
 
     GuiData {
         window,
-        statusbar,
+        spinner,
+        input,
         input_buffer,
+        editor,
+        script: Path::new(filename).canonicalize().unwrap(),
+        error_box,
+        error_label,
+        error_store,
+        error_tag,
+        frame_tag,
         klc_buffer,
+        klc_store,
+        drc_store,
+        elements_store,
         notebook,
+        variants,
+        variants_box,
+        params_frame,
+        params_grid,
+        params: RefCell::new(vec![]),
         exit,
         save,
+        save_image,
+        variant_changed,
+        params_changed,
     }
 }
 
@@ -282,3 +1169,42 @@ pub fn get_export_filename(ui: &GuiData, filename: String) -> Option<PathBuf> {
         None
     }
 }
+
+/// ask where to export an image of the view to, and at what scale
+pub fn get_image_filename(ui: &GuiData, filename: String) -> Option<(PathBuf, ImageOptions)> {
+    let d = FileChooserDialog::with_buttons(
+        Some("Export image (png, svg or pdf)"),
+        Some(&ui.window),
+        FileChooserAction::Save,
+        &[
+            ("_Cancel", ResponseType::Cancel),
+            ("_Export", ResponseType::Accept),
+        ],
+    );
+    d.set_current_name(filename);
+    let defaults = ImageOptions::default();
+    let options = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    options.pack_start(&Label::new(Some("pixels per mm:")), false, false, 0);
+    let scale = SpinButton::new_with_range(1.0, 1000.0, 1.0);
+    scale.set_value(defaults.scale);
+    options.pack_start(&scale, false, false, 0);
+    let title_block = CheckButton::new_with_label("title block");
+    title_block.set_active(defaults.title_block);
+    options.pack_start(&title_block, false, false, 0);
+    options.show_all();
+    d.set_extra_widget(&options);
+    let res: ResponseType = d.run().into();
+    let result = if res == ResponseType::Accept {
+        d.get_filename().map(|filename| {
+            let options = ImageOptions {
+                scale: scale.get_value(),
+                title_block: title_block.get_active(),
+            };
+            (filename, options)
+        })
+    } else {
+        None
+    };
+    d.destroy();
+    result
+}
@@ -16,9 +16,23 @@ pub struct Footprint {
     pub smd: Vec<Smd>,
     pub lines: Vec<Line>,
     pub rects: Vec<Rect>,
+    pub dimensions: Vec<Dimension>,
 }
 
-fn to_footprint(elements: &Vec<Element>) -> Footprint {
+/// a string in double quotes, as kicad reads it
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn at(x: f64, y: f64, rot: f64) -> String {
+    if rot == 0.0 {
+        format!("(at {} {})", x, y)
+    } else {
+        format!("(at {} {} {})", x, y, rot)
+    }
+}
+
+pub fn to_footprint(elements: &Vec<Element>) -> Footprint {
     let mut f = Footprint::default();
     for e in elements {
         e.apply_footprint(&mut f);
@@ -40,7 +54,10 @@ pub fn save(elements: &Vec<Element>, f: &mut fs::File) -> Result<(), MpError> {
     let local: DateTime<Local> = Local::now();
     let ts = local.timestamp();
     write!(f, "(module {} (layer F.Cu) (tedit {:X})\n", name.txt, ts)?;
-    write!(f, "  (tags \"\")\n")?; // TODO tags
+    if !footprint.desc.is_empty() {
+        write!(f, "  (descr {})\n", quoted(&footprint.desc))?;
+    }
+    write!(f, "  (tags {})\n", quoted(&footprint.tags))?;
     write!(f, "  (attr smd)\n")?; // TODO pth
 
     write!(
@@ -89,8 +106,8 @@ pub fn save(elements: &Vec<Element>, f: &mut fs::File) -> Result<(), MpError> {
         let shape:&'static str = pad.shape.clone().into();
         write!(
             f,
-            "  (pad {} smd {} (at {} {}) (size {} {}) (layers {}))\n",
-            pad.name, shape, pad.x, pad.y, pad.dx, pad.dy, layers
+            "  (pad {} smd {} {} (size {} {}) (layers {}))\n",
+            pad.name, shape, at(pad.x, pad.y, pad.rot), pad.dx, pad.dy, layers
         )?;
     }
 
@@ -107,44 +124,44 @@ pub fn save(elements: &Vec<Element>, f: &mut fs::File) -> Result<(), MpError> {
         };
         write!(
             f,
-            "  (pad {} {} circle (at {} {}) (size {} {}) (drill {}) (layers {}))\n",
-            pad.name, pad_type, pad.x, pad.y, pad.dx, pad.dy, pad.drill, layers
+            "  (pad {} {} circle {} (size {} {}) (drill {}) (layers {}))\n",
+            pad.name, pad_type, at(pad.x, pad.y, pad.rot), pad.dx, pad.dy, pad.drill, layers
         )?;
     }
 
     for rect in &footprint.rects {
         write!(f, "  (fp_poly (pts ")?;
+        let c = rect.corners();
+        for p in c.iter().chain(c.iter().take(1)) {
+            write!(f, "(xy {} {})", p.x, p.y)?;
+        }
+        write!(f, ") (layer {}) (width {}))\n", rect.layer, rect.w)?;
+    }
+
+    // footprints have no dimension objects, so these are plain lines and
+    // text on the layer of the dimension
+    for dim in &footprint.dimensions {
+        for (a, b) in dim.segments() {
+            write!(
+                f,
+                "  (fp_line (start {} {}) (end {} {}) (layer {}) (width {}))\n",
+                a.x, a.y, b.x, b.y, dim.layer, dim.w
+            )?;
+        }
+        let (p, rot, text) = dim.label();
         write!(
             f,
-            "(xy {} {})",
-            rect.x - rect.dx / 2.0,
-            rect.y - rect.dy / 2.0
-        )?;
-        write!(
-            f,
-            "(xy {} {})",
-            rect.x + rect.dx / 2.0,
-            rect.y - rect.dy / 2.0
-        )?;
-        write!(
-            f,
-            "(xy {} {})",
-            rect.x + rect.dx / 2.0,
-            rect.y + rect.dy / 2.0
-        )?;
-        write!(
-            f,
-            "(xy {} {})",
-            rect.x - rect.dx / 2.0,
-            rect.y + rect.dy / 2.0
+            "  (fp_text user \"{}\" {} (layer {})\n",
+            text,
+            at(p.x, p.y, rot),
+            dim.layer
         )?;
         write!(
             f,
-            "(xy {} {})",
-            rect.x - rect.dx / 2.0,
-            rect.y - rect.dy / 2.0
+            "    (effects (font (size {} {}) (thickness {})))\n",
+            dim.dy, dim.dy, dim.w
         )?;
-        write!(f, ") (layer {}) (width {}))\n", rect.layer, rect.w)?;
+        write!(f, "  )\n")?;
     }
 
     // TODO model...
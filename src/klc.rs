@@ -1,26 +1,936 @@
 // (c) 2018 Joost Yervante Damad <joost@damad.be>
 
+// KiCad Library Convention checks.
+//
+// The most important rules are checked natively on the footprint;
+// the complete check_kicad_mod.py script from the KiCad library utils
+// can still be run when it is configured.
+
+use std::collections::HashSet;
 use std::fs::File;
-use std::process::Command;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Output};
 
+use check::{pad_outlines_on, segments, ElementRef, Issue, Severity, SIDES};
+use element::{Bound, BoundingBox, Line, Rect};
 use error::MpError;
-use kicad;
+use geometry::Point;
+use kicad::{self, Footprint};
+use layers::Layer;
+use settings::Settings;
 use tempfile::tempdir;
 use DrawState;
 
-pub fn run_klc(draw_state: &DrawState, klc_dir: &str) -> Result<String, MpError> {
-    let dir = tempdir()?;
-    let name = format!("{}.kicad_mod", draw_state.name());
-    let file_path = dir.path().join(&name);
-    let mut f = File::create(&file_path)?;
-    kicad::save(&draw_state.elements, &mut f)?;
+/// allowed silkscreen line width range (F5.1)
+const SILK_WIDTH: (f64, f64) = (0.10, 0.15);
+/// minimum clearance between silkscreen and pads (F5.1)
+const SILK_PAD_CLEARANCE: f64 = 0.2;
+/// allowed fabrication line width range (F5.2)
+const FAB_WIDTH: (f64, f64) = (0.05, 0.15);
+/// courtyard line width (F5.3)
+const CRTYD_WIDTH: f64 = 0.05;
+/// courtyard coordinates are on this grid (F5.3)
+const CRTYD_GRID: f64 = 0.01;
+/// minimum clearance between the courtyard and the component (F5.3)
+const CRTYD_CLEARANCE: f64 = 0.25;
+/// minimum annular ring of plated holes (F7.5)
+const MIN_ANNULAR_RING: f64 = 0.15;
+
+const EPSILON: f64 = 1e-6;
+
+/// default interpreter for check_kicad_mod.py
+const PYTHON: &'static str = "python3";
+const SCRIPT: &'static str = "check_kicad_mod.py";
+
+fn lines_on<'a>(f: &'a Footprint, layer: Layer) -> Vec<&'a Line> {
+    f.lines.iter().filter(|l| l.layer == layer).collect()
+}
+
+fn rects_on<'a>(f: &'a Footprint, layer: Layer) -> Vec<&'a Rect> {
+    f.rects.iter().filter(|r| r.layer == layer).collect()
+}
+
+fn check_width(
+    issues: &mut Vec<Issue>,
+    f: &Footprint,
+    rule: &str,
+    layer: Layer,
+    (min, max): (f64, f64),
+) {
+    let widths = lines_on(f, layer)
+        .into_iter()
+        .map(|l| (l.w, ElementRef::line(l)))
+        .chain(rects_on(f, layer).into_iter().map(|r| (r.w, ElementRef::rect(r))));
+    for (w, element) in widths {
+        if w < min - EPSILON || w > max + EPSILON {
+            issues.push(Issue::new(
+                rule,
+                Severity::Error,
+                format!(
+                    "{} line width is {} mm, should be between {} and {} mm",
+                    layer, w, min, max
+                ),
+                Some(element),
+            ));
+        }
+    }
+}
+
+fn check_silk(issues: &mut Vec<Issue>, f: &Footprint) {
+    check_width(issues, f, "F5.1", Layer::FSilkS, SILK_WIDTH);
+    check_width(issues, f, "F5.1", Layer::BSilkS, SILK_WIDTH);
+    if let Some(ref reference) = f.reference {
+        if reference.layer != Layer::FSilkS {
+            issues.push(Issue::new(
+                "F5.1",
+                Severity::Error,
+                format!("reference is on {}, should be on F.SilkS", reference.layer),
+                Some(ElementRef::text("reference", reference)),
+            ));
+        }
+    }
+    // silkscreen is only checked against the pads on its own side
+    for &(copper, silk_layer) in &SIDES {
+        let pads = pad_outlines_on(f, copper);
+        for (a, b, w, silk) in segments(f, silk_layer) {
+            for &(ref outline, ref pad) in &pads {
+                let d = outline.segment_distance(a, b) - w / 2.0;
+                if d < SILK_PAD_CLEARANCE - EPSILON {
+                    let message = if d <= 0.0 {
+                        format!("silkscreen overlaps pad {}", pad.name)
+                    } else {
+                        format!(
+                            "silkscreen is {:.3} mm from pad {}, should be at least {} mm",
+                            d, pad.name, SILK_PAD_CLEARANCE
+                        )
+                    };
+                    issues.push(Issue::new("F5.1", Severity::Error, message, Some(silk.clone())));
+                }
+            }
+        }
+    }
+}
+
+fn check_fab(issues: &mut Vec<Issue>, f: &Footprint) {
+    if lines_on(f, Layer::FFab).is_empty() && rects_on(f, Layer::FFab).is_empty() {
+        issues.push(Issue::new(
+            "F5.2",
+            Severity::Warning,
+            "no component outline on F.Fab".into(),
+            None,
+        ));
+    }
+    check_width(issues, f, "F5.2", Layer::FFab, FAB_WIDTH);
+    if let Some(ref name) = f.name {
+        if name.layer != Layer::FFab {
+            issues.push(Issue::new(
+                "F5.2",
+                Severity::Error,
+                format!("value is on {}, should be on F.Fab", name.layer),
+                Some(ElementRef::text("value", name)),
+            ));
+        }
+    }
+}
+
+fn on_grid(v: f64) -> bool {
+    let steps = v / CRTYD_GRID;
+    (steps - steps.round()).abs() < EPSILON / CRTYD_GRID
+}
+
+fn courtyard_bound(f: &Footprint, layer: Layer) -> Option<Bound> {
+    let mut points = vec![];
+    for (a, b, _, _) in segments(f, layer) {
+        points.push(a);
+        points.push(b);
+    }
+    if points.is_empty() {
+        None
+    } else {
+        Some(Bound::from_points(&points))
+    }
+}
+
+/// courtyard, fabrication and copper layer of the front and of the back
+const COURTYARDS: [(Layer, Layer, Layer); 2] = [
+    (Layer::FCrtYd, Layer::FFab, Layer::FCu),
+    (Layer::BCrtYd, Layer::BFab, Layer::BCu),
+];
+
+fn check_courtyard(issues: &mut Vec<Issue>, f: &Footprint) {
+    for &(crtyd, fab, copper) in &COURTYARDS {
+        // the back only needs a courtyard when something is on the back
+        let on_side = copper == Layer::FCu
+            || f.smd.iter().any(|s| s.copper_layer() == copper)
+            || !segments(f, fab).is_empty()
+            || !segments(f, crtyd).is_empty();
+        if on_side {
+            check_courtyard_on(issues, f, crtyd, fab, copper);
+        }
+    }
+}
+
+fn check_courtyard_on(
+    issues: &mut Vec<Issue>,
+    f: &Footprint,
+    crtyd: Layer,
+    fab: Layer,
+    copper: Layer,
+) {
+    let courtyard = match courtyard_bound(f, crtyd) {
+        Some(courtyard) => courtyard,
+        None => {
+            issues.push(Issue::new(
+                "F5.3",
+                Severity::Error,
+                format!("no courtyard on {}", crtyd),
+                None,
+            ));
+            return;
+        }
+    };
+    for line in lines_on(f, crtyd) {
+        let points = [Point::new(line.x1, line.y1), Point::new(line.x2, line.y2)];
+        check_courtyard_shape(issues, line.w, &points, ElementRef::line(line));
+    }
+    for rect in rects_on(f, crtyd) {
+        check_courtyard_shape(issues, rect.w, &rect.corners(), ElementRef::rect(rect));
+    }
+
+    // the courtyard has to enclose the pads and the component body on
+    // its side; through hole pads are on both sides
+    let mut bounds: Vec<Bound> = vec![];
+    bounds.extend(
+        f.smd
+            .iter()
+            .filter(|s| s.copper_layer() == copper)
+            .map(|s| s.bounding_box()),
+    );
+    bounds.extend(f.pad.iter().map(|p| p.bounding_box()));
+    bounds.extend(lines_on(f, fab).iter().map(|l| l.bounding_box()));
+    bounds.extend(rects_on(f, fab).iter().map(|r| r.bounding_box()));
+    if bounds.is_empty() {
+        return;
+    }
+    let component = bounds[1..]
+        .iter()
+        .fold(bounds[0].clone(), |a, b| a.combine(b));
+    let clearance = [
+        ("left", component.min_x - courtyard.min_x),
+        ("top", component.min_y - courtyard.min_y),
+        ("right", courtyard.max_x - component.max_x),
+        ("bottom", courtyard.max_y - component.max_y),
+    ];
+    for &(side, c) in &clearance {
+        if c < CRTYD_CLEARANCE - EPSILON {
+            issues.push(Issue::new(
+                "F5.3",
+                Severity::Error,
+                format!(
+                    "{} clearance at the {} is {:.3} mm, should be at least {} mm",
+                    crtyd, side, c, CRTYD_CLEARANCE
+                ),
+                Some(ElementRef::new("courtyard", &crtyd.to_string(), courtyard.clone())),
+            ));
+        }
+    }
+}
+
+fn check_courtyard_shape(issues: &mut Vec<Issue>, w: f64, points: &[Point], crtyd: ElementRef) {
+    if (w - CRTYD_WIDTH).abs() > EPSILON {
+        issues.push(Issue::new(
+            "F5.3",
+            Severity::Error,
+            format!("courtyard line width is {} mm, should be {} mm", w, CRTYD_WIDTH),
+            Some(crtyd.clone()),
+        ));
+    }
+    if !points.iter().all(|p| on_grid(p.x) && on_grid(p.y)) {
+        issues.push(Issue::new(
+            "F5.3",
+            Severity::Error,
+            format!("courtyard is not on a {} mm grid", CRTYD_GRID),
+            Some(crtyd),
+        ));
+    }
+}
+
+fn same_layers(layers: &[Layer], expected: &[Layer]) -> bool {
+    let a: HashSet<&Layer> = layers.iter().collect();
+    let b: HashSet<&Layer> = expected.iter().collect();
+    a == b
+}
+
+fn layer_names(layers: &[Layer]) -> String {
+    layers
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn check_pads(issues: &mut Vec<Issue>, f: &Footprint) {
+    for smd in &f.smd {
+        let smd_layers = if smd.copper_layer() == Layer::BCu {
+            [Layer::BCu, Layer::BPaste, Layer::BMask]
+        } else {
+            [Layer::FCu, Layer::FPaste, Layer::FMask]
+        };
+        if !same_layers(&smd.layers, &smd_layers) {
+            issues.push(Issue::new(
+                "F6.3",
+                Severity::Error,
+                format!(
+                    "SMD pad is on layers {}, should be on {}",
+                    layer_names(&smd.layers),
+                    layer_names(&smd_layers)
+                ),
+                Some(ElementRef::smd(smd)),
+            ));
+        }
+    }
+    let tht_layers = [Layer::Cu, Layer::Mask];
+    for pad in &f.pad {
+        if !same_layers(&pad.layers, &tht_layers) {
+            issues.push(Issue::new(
+                "F7.4",
+                Severity::Error,
+                format!(
+                    "through hole pad is on layers {}, should be on {}",
+                    layer_names(&pad.layers),
+                    layer_names(&tht_layers)
+                ),
+                Some(ElementRef::pad(pad)),
+            ));
+        }
+        if pad.plated {
+            let ring = (pad.dx.min(pad.dy) - pad.drill) / 2.0;
+            if ring < MIN_ANNULAR_RING - EPSILON {
+                issues.push(Issue::new(
+                    "F7.5",
+                    Severity::Error,
+                    format!(
+                        "annular ring is {:.3} mm, should be at least {} mm",
+                        ring, MIN_ANNULAR_RING
+                    ),
+                    Some(ElementRef::pad(pad)),
+                ));
+            }
+        }
+    }
+}
+
+fn check_metadata(issues: &mut Vec<Issue>, f: &Footprint) {
+    if f.name.is_none() {
+        issues.push(Issue::new(
+            "F9.1",
+            Severity::Error,
+            "footprint has no name".into(),
+            None,
+        ));
+    }
+    if f.reference.is_none() {
+        issues.push(Issue::new(
+            "F9.1",
+            Severity::Error,
+            "footprint has no reference".into(),
+            None,
+        ));
+    }
+    if f.desc.is_empty() {
+        issues.push(Issue::new(
+            "F9.3",
+            Severity::Warning,
+            "footprint has no description".into(),
+            None,
+        ));
+    }
+    if f.tags.is_empty() {
+        issues.push(Issue::new(
+            "F9.3",
+            Severity::Warning,
+            "footprint has no keywords".into(),
+            None,
+        ));
+    }
+}
+
+/// check a footprint against the KLC rules that are implemented natively
+pub fn check(f: &Footprint) -> Vec<Issue> {
+    let mut issues = vec![];
+    check_silk(&mut issues, f);
+    check_fab(&mut issues, f);
+    check_courtyard(&mut issues, f);
+    check_pads(&mut issues, f);
+    check_metadata(&mut issues, f);
+    issues
+}
+
+/// find the element a message of check_kicad_mod.py is about: a pad
+/// mentioned by name, or the courtyard
+fn resolve(message: &str, f: &Footprint) -> Option<ElementRef> {
+    let words = message
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .collect::<Vec<&str>>();
+    for pair in words.windows(2) {
+        if pair[0].eq_ignore_ascii_case("pad") {
+            if let Some(smd) = f.smd.iter().find(|s| s.name == pair[1]) {
+                return Some(ElementRef::smd(smd));
+            }
+            if let Some(pad) = f.pad.iter().find(|p| p.name == pair[1]) {
+                return Some(ElementRef::pad(pad));
+            }
+        }
+    }
+    if message.to_lowercase().contains("courtyard") {
+        return courtyard_bound(f, Layer::FCrtYd)
+            .map(|b| ElementRef::new("courtyard", "F.CrtYd", b));
+    }
+    None
+}
+
+/// rule id like F5.3 in a header line of check_kicad_mod.py
+fn rule_id(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .find(|w| {
+            let mut chars = w.chars();
+            chars.next().map_or(false, |c| c.is_ascii_uppercase())
+                && chars.as_str().contains('.')
+                && chars.all(|c| c.is_ascii_digit() || c == '.')
+        })
+        .map(|w| w.to_string())
+}
+
+/// parse the output of `check_kicad_mod.py --nocolor -v`
+///
+/// Every violated rule starts with a "Violating F5.3 - ..." header
+/// (or "Warning ..." for warnings), followed by more indented lines
+/// with the details.
+fn parse_output(output: &str, f: &Footprint) -> Vec<Issue> {
+    let mut issues: Vec<Issue> = vec![];
+    // indentation of the last header, details are indented more
+    let mut header_indent = None;
+    for line in output.lines() {
+        let text = line.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let indent = line.len() - line.trim_left().len();
+        let severity = if text.starts_with("Violating") {
+            Some(Severity::Error)
+        } else if text.to_lowercase().starts_with("warning") {
+            Some(Severity::Warning)
+        } else {
+            None
+        };
+        if let (Some(severity), Some(rule)) = (severity, rule_id(text)) {
+            issues.push(Issue::new(&rule, severity, text.into(), None));
+            header_indent = Some(indent);
+            continue;
+        }
+        match (header_indent, issues.last_mut()) {
+            (Some(h), Some(issue)) if indent > h => {
+                issue.message.push('\n');
+                issue.message.push_str(text);
+                if issue.element.is_none() {
+                    issue.element = resolve(text, f);
+                }
+            }
+            _ => header_indent = None,
+        }
+    }
+    issues
+}
+
+/// result of running check_kicad_mod.py
+pub struct KlcOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// the rule violations the script reported
+    pub issues: Vec<Issue>,
+    /// why the script could not be run or failed
+    pub failure: Option<String>,
+}
+
+impl KlcOutput {
+    /// what the script run as command said about footprint f
+    fn new(command: &str, output: &Output, f: &Footprint) -> KlcOutput {
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let issues = parse_output(&stdout, f);
+        // the script exits with an error when it finds violations, so the exit
+        // status only means something when there is nothing to show for it
+        let failure = if stderr.contains("Traceback (most recent call last)") {
+            let error = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+            Some(format!("{} crashed: {}", command, error.trim()))
+        } else if !output.status.success() && issues.is_empty() {
+            Some(format!(
+                "{} failed without reporting any violations: {}",
+                command, output.status
+            ))
+        } else {
+            None
+        };
+        KlcOutput {
+            stdout,
+            stderr,
+            issues,
+            failure,
+        }
+    }
+
+    fn failed(failure: String) -> KlcOutput {
+        KlcOutput {
+            stdout: String::new(),
+            stderr: String::new(),
+            issues: vec![],
+            failure: Some(failure),
+        }
+    }
+
+    /// everything the script said, for the KLC tab
+    pub fn text(&self) -> String {
+        let mut text = self.stdout.clone();
+        if !self.stderr.is_empty() {
+            text.push_str("\nstderr:\n");
+            text.push_str(&self.stderr);
+        }
+        if let Some(ref failure) = self.failure {
+            text.push_str(&format!("\n{}\n", failure));
+        }
+        text
+    }
+}
+
+/// run check_kicad_mod.py from the KiCad library utils in klc_dir
+pub fn run_klc(draw_state: &DrawState, klc_dir: &str, settings: &Settings) -> KlcOutput {
+    let python = settings.klc_python.clone().unwrap_or_else(|| PYTHON.into());
+    let script = settings.klc_script.clone().unwrap_or_else(|| SCRIPT.into());
+    if !Path::new(klc_dir).join(&script).exists() {
+        return KlcOutput::failed(format!("KLC script {} not found in {}", script, klc_dir));
+    }
+    let dir = match tempdir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            return KlcOutput::failed(format!("Failed to create temporary directory: {}", e));
+        }
+    };
+    let file_path = dir.path().join(format!("{}.kicad_mod", draw_state.file_name()));
+    let saved = File::create(&file_path)
+        .map_err(MpError::from)
+        .and_then(|mut f| kicad::save(&draw_state.elements, &mut f));
+    if let Err(e) = saved {
+        return KlcOutput::failed(format!("Failed to save footprint for KLC: {:?}", e));
+    }
     info!("Temp file: {}", file_path.display());
-    let output = Command::new("/usr/bin/python")
+    let output = Command::new(&python)
         .current_dir(klc_dir)
-        .arg("check_kicad_mod.py")
+        .arg(&script)
         .arg("--nocolor")
         .arg("-v")
-        .arg(&format!("{}", file_path.display()))
-        .output()?;
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        .args(&settings.klc_args)
+        .arg(&file_path)
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            return KlcOutput::failed(format!(
+                "Python interpreter '{}' not found, set klc_python in ~/.madparts.conf",
+                python
+            ));
+        }
+        Err(e) => {
+            return KlcOutput::failed(format!("Failed to run {} {}: {}", python, script, e));
+        }
+    };
+    let command = format!("{} {}", python, script);
+    KlcOutput::new(&command, &output, &kicad::to_footprint(&draw_state.elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use element::{Pad, Smd, SmdShape, Text};
+
+    fn text(txt: &str, layer: Layer) -> Text {
+        Text {
+            x: 0.0,
+            y: 0.0,
+            dy: 1.0,
+            txt: txt.into(),
+            thickness: 0.15,
+            shorten: None,
+            layer,
+        }
+    }
+
+    fn line(x1: f64, y1: f64, x2: f64, y2: f64, w: f64, layer: Layer) -> Line {
+        Line {
+            x1,
+            y1,
+            x2,
+            y2,
+            w,
+            layer,
+        }
+    }
+
+    fn rect(dx: f64, dy: f64, w: f64, layer: Layer) -> Rect {
+        Rect {
+            x: 0.0,
+            y: 0.0,
+            dx,
+            dy,
+            w,
+            filled: false,
+            layer,
+            corner: None,
+            rot: 0.0,
+        }
+    }
+
+    fn smd(name: &str, x: f64, layers: &[Layer]) -> Smd {
+        Smd {
+            name: name.into(),
+            x,
+            y: 0.0,
+            dx: 0.5,
+            dy: 0.5,
+            layers: layers.to_vec(),
+            shape: SmdShape::Rect,
+            rot: 0.0,
+            allow_duplicate: false,
+        }
+    }
+
+    const FRONT: [Layer; 3] = [Layer::FCu, Layer::FPaste, Layer::FMask];
+    const BACK: [Layer; 3] = [Layer::BCu, Layer::BPaste, Layer::BMask];
+
+    fn pad(dx: f64, drill: f64) -> Pad {
+        Pad {
+            name: "3".into(),
+            x: 0.0,
+            y: 2.0,
+            dx,
+            dy: dx,
+            drill,
+            layers: vec![Layer::Cu, Layer::Mask],
+            plated: true,
+            rot: 0.0,
+            allow_duplicate: false,
+        }
+    }
+
+    /// a footprint without any issues; the courtyard is exactly at
+    /// the minimum clearance around the fab outline
+    fn good() -> Footprint {
+        Footprint {
+            name: Some(text("GOOD", Layer::FFab)),
+            reference: Some(text("REF**", Layer::FSilkS)),
+            desc: "a good footprint".into(),
+            tags: "good".into(),
+            pad: vec![],
+            smd: vec![smd("1", -1.0, &FRONT), smd("2", 1.0, &FRONT)],
+            lines: vec![line(-1.0, -1.0, 1.0, -1.0, 0.12, Layer::FSilkS)],
+            rects: vec![rect(3.0, 1.0, 0.1, Layer::FFab), rect(3.6, 1.6, 0.05, Layer::FCrtYd)],
+            dimensions: vec![],
+        }
+    }
+
+    fn messages(f: &Footprint, rule: &str) -> Vec<String> {
+        check(f)
+            .into_iter()
+            .filter(|i| i.rule == rule)
+            .map(|i| i.message)
+            .collect()
+    }
+
+    #[test]
+    fn good_footprint() {
+        let issues = check(&good());
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn silk_width() {
+        let mut f = good();
+        f.lines[0].w = 0.2;
+        assert_eq!(
+            messages(&f, "F5.1"),
+            vec!["F.SilkS line width is 0.2 mm, should be between 0.1 and 0.15 mm"]
+        );
+    }
+
+    #[test]
+    fn reference_layer() {
+        let mut f = good();
+        f.reference = Some(text("REF**", Layer::FFab));
+        assert_eq!(
+            messages(&f, "F5.1"),
+            vec!["reference is on F.Fab, should be on F.SilkS"]
+        );
+    }
+
+    #[test]
+    fn silk_pad_clearance() {
+        let mut f = good();
+        f.lines[0].y1 = -0.4;
+        f.lines[0].y2 = -0.4;
+        assert_eq!(messages(&f, "F5.1").len(), 2);
+        assert!(messages(&f, "F5.1")[0].starts_with("silkscreen is 0.090 mm from pad"));
+        f.lines[0].y1 = 0.0;
+        f.lines[0].y2 = 0.0;
+        assert!(messages(&f, "F5.1")[0].starts_with("silkscreen overlaps pad"));
+        // back silkscreen doesn't care about front pads
+        f.lines[0].layer = Layer::BSilkS;
+        assert!(messages(&f, "F5.1").is_empty());
+    }
+
+    #[test]
+    fn fab() {
+        let mut f = good();
+        f.rects[0].w = 0.2;
+        f.name = Some(text("GOOD", Layer::FSilkS));
+        assert_eq!(
+            messages(&f, "F5.2"),
+            vec![
+                "F.Fab line width is 0.2 mm, should be between 0.05 and 0.15 mm",
+                "value is on F.SilkS, should be on F.Fab",
+            ]
+        );
+        f.rects.remove(0);
+        assert!(messages(&f, "F5.2").contains(&"no component outline on F.Fab".to_string()));
+    }
+
+    #[test]
+    fn courtyard_missing() {
+        let mut f = good();
+        f.rects.remove(1);
+        assert_eq!(messages(&f, "F5.3"), vec!["no courtyard on F.CrtYd"]);
+    }
+
+    #[test]
+    fn courtyard_shape() {
+        let mut f = good();
+        f.rects[1].w = 0.1;
+        f.rects[1].dx = 3.605;
+        assert_eq!(
+            messages(&f, "F5.3"),
+            vec![
+                "courtyard line width is 0.1 mm, should be 0.05 mm",
+                "courtyard is not on a 0.01 mm grid",
+            ]
+        );
+    }
+
+    #[test]
+    fn courtyard_clearance() {
+        let mut f = good();
+        f.rects[1].dx = 3.4;
+        assert_eq!(
+            messages(&f, "F5.3"),
+            vec![
+                "F.CrtYd clearance at the left is 0.150 mm, should be at least 0.25 mm",
+                "F.CrtYd clearance at the right is 0.150 mm, should be at least 0.25 mm",
+            ]
+        );
+    }
+
+    #[test]
+    fn back_courtyard() {
+        let mut f = good();
+        f.smd.push(smd("3", 0.0, &BACK));
+        f.smd[2].y = 2.0;
+        assert_eq!(messages(&f, "F5.3"), vec!["no courtyard on B.CrtYd"]);
+        let mut crtyd = rect(3.6, 1.6, 0.05, Layer::BCrtYd);
+        crtyd.y = 2.0;
+        f.rects.push(crtyd);
+        assert!(messages(&f, "F5.3").is_empty());
+        // the back courtyard has to enclose the back pads
+        f.smd[2].x = 1.6;
+        assert_eq!(
+            messages(&f, "F5.3"),
+            vec!["B.CrtYd clearance at the right is -0.050 mm, should be at least 0.25 mm"]
+        );
+    }
+
+    #[test]
+    fn smd_layers() {
+        let mut f = good();
+        f.smd[0].layers = vec![Layer::FCu, Layer::FMask];
+        assert_eq!(
+            messages(&f, "F6.3"),
+            vec!["SMD pad is on layers F.Cu F.Mask, should be on F.Cu F.Paste F.Mask"]
+        );
+    }
+
+    #[test]
+    fn tht_pads() {
+        let mut f = good();
+        f.rects[1].y = 1.0;
+        f.rects[1].dy = 3.6;
+        f.pad.push(pad(1.0, 0.6));
+        assert!(check(&f).is_empty(), "{:?}", check(&f));
+        f.pad[0].layers = vec![Layer::FCu, Layer::Mask];
+        f.pad[0].drill = 0.8;
+        assert_eq!(
+            messages(&f, "F7.4"),
+            vec!["through hole pad is on layers F.Cu *.Mask, should be on *.Cu *.Mask"]
+        );
+        assert_eq!(
+            messages(&f, "F7.5"),
+            vec!["annular ring is 0.100 mm, should be at least 0.15 mm"]
+        );
+        // a non plated hole has no ring
+        f.pad[0].plated = false;
+        assert!(messages(&f, "F7.5").is_empty());
+    }
+
+    #[test]
+    fn metadata() {
+        let mut f = good();
+        f.name = None;
+        f.reference = None;
+        f.desc.clear();
+        f.tags.clear();
+        assert_eq!(
+            messages(&f, "F9.1"),
+            vec!["footprint has no name", "footprint has no reference"]
+        );
+        assert_eq!(
+            messages(&f, "F9.3"),
+            vec!["footprint has no description", "footprint has no keywords"]
+        );
+    }
+
+    /// check_kicad_mod.py --nocolor -v of a footprint without issues
+    const CLEAN: &str = "Checking footprint 'GOOD':\n";
+
+    /// check_kicad_mod.py --nocolor -v of a footprint with issues
+    const VIOLATIONS: &str = "Checking footprint 'GOOD':
+  Violating F5.3 - Courtyard layer requirements
+    Courtyard line width is incorrect
+      - Courtyard lines should be 0.05mm wide
+  Violating F7.5 - Annular ring
+    Pad '3' has an annular ring of 0.10mm
+      - Minimum annular ring is 0.15mm
+  Warning F9.3 - Footprint meta-data is filled in as appropriate
+    Description should contain the datasheet link
+Could not find datasheet
+";
+
+    fn output(code: i32, stdout: &str, stderr: &str) -> Output {
+        use std::os::unix::process::ExitStatusExt;
+        Output {
+            status: ExitStatusExt::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    fn with_pad() -> Footprint {
+        let mut f = good();
+        f.pad.push(pad(1.0, 0.8));
+        f
+    }
+
+    #[test]
+    fn rule_ids() {
+        assert_eq!(
+            rule_id("Violating F5.3 - Courtyard layer requirements"),
+            Some("F5.3".into())
+        );
+        assert_eq!(rule_id("Violating rule F7.5:"), Some("F7.5".into()));
+        assert_eq!(rule_id("Checking footprint 'GOOD':"), None);
+        // layer names and plain numbers are no rules
+        assert_eq!(rule_id("Warning: use F.Cu, not 1.5"), None);
+    }
+
+    #[test]
+    fn resolve_elements() {
+        let f = with_pad();
+        let pad = resolve("Pad '3' has an annular ring of 0.10mm", &f).unwrap();
+        assert_eq!((pad.kind.as_str(), pad.name.as_str()), ("pad", "3"));
+        let smd = resolve("pad 2 is too small", &f).unwrap();
+        assert_eq!((smd.kind.as_str(), smd.name.as_str()), ("pad", "2"));
+        let courtyard = resolve("Courtyard line width is incorrect", &f).unwrap();
+        assert_eq!(courtyard.name, "F.CrtYd");
+        assert!((courtyard.bound.max_x - 1.8).abs() < EPSILON);
+        assert!(resolve("pad 9 does not exist", &f).is_none());
+        assert!(resolve("Description should contain the datasheet link", &f).is_none());
+    }
+
+    #[test]
+    fn clean_output() {
+        let f = with_pad();
+        assert!(parse_output(CLEAN, &f).is_empty());
+        let klc = KlcOutput::new("check", &output(0, CLEAN, ""), &f);
+        assert!(klc.issues.is_empty());
+        assert!(klc.failure.is_none());
+        assert_eq!(klc.text(), CLEAN);
+    }
+
+    #[test]
+    fn violations_output() {
+        let f = with_pad();
+        let klc = KlcOutput::new("check", &output(1, VIOLATIONS, ""), &f);
+        assert!(klc.failure.is_none());
+        let issues = klc
+            .issues
+            .iter()
+            .map(|i| (i.rule.as_str(), i.severity, i.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    "F5.3",
+                    Severity::Error,
+                    "Violating F5.3 - Courtyard layer requirements\n\
+                     Courtyard line width is incorrect\n\
+                     - Courtyard lines should be 0.05mm wide",
+                ),
+                (
+                    "F7.5",
+                    Severity::Error,
+                    "Violating F7.5 - Annular ring\n\
+                     Pad '3' has an annular ring of 0.10mm\n\
+                     - Minimum annular ring is 0.15mm",
+                ),
+                (
+                    "F9.3",
+                    Severity::Warning,
+                    "Warning F9.3 - Footprint meta-data is filled in as appropriate\n\
+                     Description should contain the datasheet link",
+                ),
+            ]
+        );
+        let elements = klc
+            .issues
+            .iter()
+            .map(|i| i.element.as_ref().map(|e| e.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            elements,
+            vec![Some("courtyard F.CrtYd".into()), Some("pad 3".into()), None]
+        );
+    }
+
+    #[test]
+    fn failed_output() {
+        let f = with_pad();
+        let klc = KlcOutput::new("check", &output(2, "", "usage: check\n"), &f);
+        assert!(klc
+            .failure
+            .unwrap()
+            .starts_with("check failed without reporting any violations: exit"));
+        let crash = "Traceback (most recent call last):\n  File \"check\", line 1\n\
+                     KeyError: 'pad'\n\n";
+        let klc = KlcOutput::new("check", &output(1, VIOLATIONS, crash), &f);
+        assert_eq!(klc.issues.len(), 3);
+        assert_eq!(klc.failure, Some("check crashed: KeyError: 'pad'".into()));
+    }
 }
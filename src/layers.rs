@@ -1,8 +1,8 @@
 use cairo;
-use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct Color {
     red: f64,
     green: f64,
@@ -10,7 +10,7 @@ pub struct Color {
     alpha: f64,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Layer {
     Background,
     Grid,
@@ -31,6 +31,20 @@ pub enum Layer {
     Mask,
     #[serde(rename = "F.Paste")]
     FPaste,
+    #[serde(rename = "B.Cu")]
+    BCu,
+    #[serde(rename = "B.SilkS")]
+    BSilkS,
+    #[serde(rename = "B.Fab")]
+    BFab,
+    #[serde(rename = "B.CrtYd")]
+    BCrtYd,
+    #[serde(rename = "B.Mask")]
+    BMask,
+    #[serde(rename = "B.Paste")]
+    BPaste,
+    #[serde(rename = "Dwgs.User")]
+    DwgsUser,
 }
 
 impl fmt::Display for Layer {
@@ -47,173 +61,143 @@ impl fmt::Display for Layer {
             Layer::FMask => write!(f, "F.Mask"),
             Layer::Mask => write!(f, "*.Mask"),
             Layer::FPaste => write!(f, "F.Paste"),
+            Layer::BCu => write!(f, "B.Cu"),
+            Layer::BSilkS => write!(f, "B.SilkS"),
+            Layer::BFab => write!(f, "B.Fab"),
+            Layer::BCrtYd => write!(f, "B.CrtYd"),
+            Layer::BMask => write!(f, "B.Mask"),
+            Layer::BPaste => write!(f, "B.Paste"),
+            Layer::DwgsUser => write!(f, "Dwgs.User"),
         }
     }
 }
 
-pub struct LayerStat {
-    pub color: Color,
-    pub z: i64,
+/// the layers footprint elements can be put on
+pub const FOOTPRINT_LAYERS: &'static [Layer] = &[
+    Layer::FCu,
+    Layer::Cu,
+    Layer::FSilkS,
+    Layer::FFab,
+    Layer::FCrtYd,
+    Layer::FMask,
+    Layer::Mask,
+    Layer::FPaste,
+    Layer::BCu,
+    Layer::BSilkS,
+    Layer::BFab,
+    Layer::BCrtYd,
+    Layer::BMask,
+    Layer::BPaste,
+    Layer::DwgsUser,
+];
+
+impl Layer {
+    /// layers on the front side only, dimmed when viewing the back
+    pub fn is_front(&self) -> bool {
+        match *self {
+            Layer::FCu
+            | Layer::FSilkS
+            | Layer::FFab
+            | Layer::FCrtYd
+            | Layer::FMask
+            | Layer::FPaste => true,
+            _ => false,
+        }
+    }
+
+    /// an element on this layer is drawn on the view layer; *.Mask has
+    /// no view layer of its own, it is drawn on both mask layers
+    pub fn shows_on(&self, layer: Layer) -> bool {
+        *self == layer
+            || (*self == Layer::Mask && (layer == Layer::FMask || layer == Layer::BMask))
+    }
+}
+
+impl FromStr for Layer {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Layer, ()> {
+        FOOTPRINT_LAYERS
+            .iter()
+            .find(|l| l.to_string() == s)
+            .cloned()
+            .ok_or(())
+    }
+}
+
+/// how a layer is shown in the view
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayerView {
+    pub visible: bool,
+    /// 0.0 is transparent, 1.0 uses the layer color as is
+    pub opacity: f64,
+}
+
+impl Default for LayerView {
+    fn default() -> LayerView {
+        LayerView {
+            visible: true,
+            opacity: 1.0,
+        }
+    }
 }
 
 impl Color {
     pub fn set_source(&self, cr: &cairo::Context) {
         cr.set_source_rgba(self.red, self.green, self.blue, self.alpha);
     }
-}
 
-// kicad: see in common/colors.cpp
-// and common/class_colors_design_settings.cpp
-
-lazy_static! {
-    pub static ref LAYER: HashMap<Layer, LayerStat> = {
-        let mut m = HashMap::new();
-        m.insert(
-            Layer::Background,
-            LayerStat {
-                color: Color {
-                    red: 0.0,
-                    green: 0.0,
-                    blue: 0.0,
-                    alpha: 1.0,
-                },
-                z: -100,
-            },
-        );
-        m.insert(
-            Layer::Grid,
-            LayerStat {
-                color: Color {
-                    red: 0.52,
-                    green: 0.52,
-                    blue: 0.52,
-                    alpha: 1.0,
-                },
-                z: -90,
-            },
-        );
-        m.insert(
-            Layer::Axes,
-            LayerStat {
-                color: Color {
-                    red: 0.0,
-                    green: 0.0,
-                    blue: 0.52,
-                    alpha: 1.0,
-                },
-                z: -80,
-            },
-        );
+    /// the same color, with the alpha multiplied by factor
+    pub fn with_alpha(&self, factor: f64) -> Color {
+        Color {
+            alpha: self.alpha * factor,
+            ..*self
+        }
+    }
+}
 
-        let color = Color {
-            red: 1.0,
-            green: 0.0,
-            blue: 0.0,
-            alpha: 0.52,
-        };
-        m.insert(Layer::FCu, LayerStat { color, z: 1 });
-        m.insert(Layer::Cu, LayerStat { color, z: 1 });
+impl From<(f64, f64, f64, f64)> for Color {
+    fn from(c: (f64, f64, f64, f64)) -> Color {
+        Color {
+            red: c.0,
+            green: c.1,
+            blue: c.2,
+            alpha: c.3,
+        }
+    }
+}
 
-        m.insert(
-            Layer::FMask,
-            LayerStat {
-                color: Color {
-                    red: 0.0,
-                    green: 0.00,
-                    blue: 1.00,
-                    alpha: 0.52,
-                },
-                z: 8,
-            },
-        );
-        m.insert(
-            Layer::FPaste,
-            LayerStat {
-                color: Color {
-                    red: 1.0,
-                    green: 0.82,
-                    blue: 0.26,
-                    alpha: 0.83,
-                },
-                z: 9,
-            },
-        );
-        m.insert(
-            Layer::FSilkS,
-            LayerStat {
-                color: Color {
-                    red: 1.0,
-                    green: 1.0,
-                    blue: 1.0,
-                    alpha: 0.83,
-                },
-                z: 11,
-            },
-        );
-        m.insert(
-            Layer::FFab,
-            LayerStat {
-                color: Color {
-                    red: 1.0,
-                    green: 1.0,
-                    blue: 0.0,
-                    alpha: 0.76,
-                },
-                z: 12,
-            },
-        );
-        m.insert(
-            Layer::FCrtYd,
-            LayerStat {
-                color: Color {
-                    red: 0.5,
-                    green: 0.5,
-                    blue: 0.5,
-                    alpha: 0.76,
-                },
-                z: 13,
-            },
-        );
+/// drawing order of the layers, the lowest z is drawn first; the colors
+/// come from the color scheme, see scheme.rs
+const LAYER_ORDER: &'static [(Layer, i64)] = &[
+    (Layer::Background, -100),
+    (Layer::Grid, -90),
+    (Layer::Axes, -80),
+    // back layers are below the front layers, in mirrored order
+    (Layer::BCrtYd, -13),
+    (Layer::BFab, -12),
+    (Layer::BSilkS, -11),
+    (Layer::BPaste, -9),
+    (Layer::BMask, -8),
+    (Layer::BCu, -1),
+    (Layer::FCu, 1),
+    (Layer::Cu, 1),
+    (Layer::FMask, 8),
+    (Layer::FPaste, 9),
+    (Layer::FSilkS, 11),
+    (Layer::FFab, 12),
+    (Layer::FCrtYd, 13),
+    (Layer::DwgsUser, 14),
+];
 
-        m
-    };
+lazy_static! {
     pub static ref LAYER_Z: Vec<(i64, Layer)> = {
-        let mut v = vec![];
-        for (ref k, ref x) in LAYER.iter() {
-            v.push((x.z, **k));
-        }
+        let mut v = LAYER_ORDER
+            .iter()
+            .map(|&(layer, z)| (z, layer))
+            .collect::<Vec<(i64, Layer)>>();
         v.sort_by(|(i, _), (j, _)| i.cmp(j));
         v
     };
 }
-
-/*
-color_schemes['default'] = {
-  'background': (0.0, 0.0, 0.0, 1.0),
-  'grid': (0.5, 0.5, 0.5, 1.0),
-  'axes': (1.0, 0.0, 0.0, 1.0),
-  'name': (1.0, 1.0, 1.0, 1.0),
-  'value': (1.0, 1.0, 1.0, 1.0),
-  'silk': (1.0, 1.0, 1.0, 1.0),
-  'bsilk': (0.7, 0.7, 0.7, 0.3),
-  'docu': (1.0, 1.0, 0.0, 0.7),
-  'smd': (0.0, 0.0, 1.0, 1.0),
-  'pad': (0.0, 1.0, 0.0, 1.0),
-  'meta':  (1.0, 1.0, 1.0, 1.0),
-  'restrict':  (0.0, 1.0, 0.0, 0.3),
-  'stop':  (0.0, 1.0, 1.0, 0.3),
-  'keepout':  (1.0, 0.0, 0.5, 0.7),
-  'bkeepout':  (0.7, 0.0, 0.35, 0.4),
-  'vrestrict':  (0.0, 1.0, 0.0, 0.4),
-  'unknown':  (1.0, 0.0, 1.0, 0.7),
-  'hole': (1.0, 1.0, 1.0, 0.7),
-  'edge': (1.0, 1.0, 1.0, 0.7),
-  'paste': (0.0, 1.0, 0.0, 0.7),
-  'bpaste': (0.0, 0.7, 0.0, 0.3),
-  'comments': (0.82, 0.66, 0.63, 0.8),
-  'assembly': (0.9, 0.6, 0.3, 0.8),
-  'bassembly': (0.65, 0.5, 0.2, 0.5),
-  'user1': (0.3, 0.6, 0.0, 0.7),
-  'user2': (0.3, 0.0, 0.6, 0.7)
-  }
-*/
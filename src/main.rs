@@ -3,9 +3,11 @@
 #![feature(specialization, const_fn, try_from)]
 
 extern crate cairo;
+extern crate cairo_sys;
 extern crate chrono;
 extern crate clap;
 extern crate env_logger;
+extern crate gdk;
 extern crate gdk_pixbuf;
 extern crate gio;
 extern crate glib;
@@ -13,6 +15,7 @@ extern crate gtk;
 extern crate inotify;
 #[macro_use]
 extern crate lazy_static;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate pyo3;
@@ -23,39 +26,50 @@ extern crate serde_json;
 extern crate serde_derive;
 extern crate tempfile;
 
-use std::convert::TryFrom;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 
-use inotify::{Inotify, WatchMask};
-
-use pyo3::{ObjectProtocol, PyList, Python};
+use pyo3::Python;
 
+use element::BoundingBox;
 use error::MpError;
+use python::EvalError;
 
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-const PRELUDEPY: &'static str = include_str!("prelude.py");
-
 #[derive(Debug, Default)]
 pub struct DrawState {
     pub bound: element::Bound,
     pub elements: Vec<element::Element>,
+    /// area marked on top of the drawing, e.g. the element of a KLC issue
+    pub highlight: Option<element::Bound>,
+    /// index of the element under the pointer, marked lighter
+    pub hover: Option<usize>,
+    /// zoomed or panned view from mm to pixels, None to fit the footprint
+    pub view: Option<geometry::Transform>,
+    /// view from the back, mirrored
+    pub back: bool,
+    /// measurement from the first to the second point, in mm
+    pub ruler: Option<(geometry::Point, geometry::Point)>,
+    /// bounding box of every element, for hit testing
+    pub bounds: Vec<element::Bound>,
+    /// layers that are hidden or drawn translucent
+    pub layers: HashMap<layers::Layer, layers::LayerView>,
+    pub grid: grid::Grid,
 }
 
 impl DrawState {
     fn name(&self) -> String {
-        for e in &self.elements {
-            match e {
-                element::Element::Name(x) => return x.text.txt.clone(),
-                _ => (),
-            }
-        }
-        "NAME".into()
+        element::footprint_name(&self.elements)
+    }
+
+    fn file_name(&self) -> String {
+        element::file_name(&self.elements)
     }
 }
 
@@ -66,15 +80,263 @@ fn main() -> Result<(), MpError> {
         .version(VERSION)
         .author("Joost Yervante Damad <joost@damad.be>")
         .about("a functional footprint editor")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+            Arg::with_name("sandbox")
+                .long("sandbox")
+                .global(true)
+                .help("Evaluate untrusted python files in a restricted sandbox"),
+        )
         .arg(
             Arg::with_name("INPUT")
                 .help("Sets the python file to use")
                 .required(true)
                 .index(1),
         )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("export all footprints of a python file without starting the GUI")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Sets the python file to use")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .short("o")
+                        .long("output-dir")
+                        .takes_value(true)
+                        .help("Directory to write the .kicad_mod files to (default: .)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("render all footprints of a python file to images without starting the GUI")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Sets the python file to use")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .short("o")
+                        .long("output-dir")
+                        .takes_value(true)
+                        .help("Directory to write the images to (default: .)"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["png", "svg", "pdf"])
+                        .help("Image format (default: png)"),
+                )
+                .arg(
+                    Arg::with_name("scale")
+                        .short("s")
+                        .long("scale")
+                        .takes_value(true)
+                        .help("Pixels per mm, points per mm for svg and pdf (default: 20)"),
+                )
+                .arg(
+                    Arg::with_name("no-title-block")
+                        .long("no-title-block")
+                        .help("Leave out the dimensions and the title block"),
+                )
+                .arg(
+                    Arg::with_name("back")
+                        .long("back")
+                        .help("Show the footprint as seen from the back"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("worker")
+                .about("evaluate a python file for the GUI")
+                .setting(AppSettings::Hidden)
+                .arg(Arg::with_name("INPUT").required(true).index(1)),
+        )
         .get_matches();
 
+    let mut settings = settings::load_settings();
+    if matches.is_present("sandbox") {
+        settings.sandbox = true;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("export") {
+        let filename = matches.value_of("INPUT").unwrap();
+        let dir = matches.value_of("dir").unwrap_or(".");
+        if matches.is_present("sandbox") {
+            settings.sandbox = true;
+        }
+        return export(filename, Path::new(dir), &settings);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("render") {
+        let filename = matches.value_of("INPUT").unwrap();
+        let dir = matches.value_of("dir").unwrap_or(".");
+        let format = matches.value_of("format").unwrap_or("png");
+        let mut options = render::ImageOptions::default();
+        if let Some(scale) = matches.value_of("scale") {
+            options.scale = scale
+                .parse()
+                .map_err(|_| MpError::Other(format!("invalid scale: {}", scale)))?;
+        }
+        options.title_block = !matches.is_present("no-title-block");
+        if matches.is_present("sandbox") {
+            settings.sandbox = true;
+        }
+        let back = matches.is_present("back");
+        return render_images(filename, Path::new(dir), format, back, &options, &settings);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("worker") {
+        return worker::run(matches.value_of("INPUT").unwrap());
+    }
+
     let filename = matches.value_of("INPUT").unwrap();
+    run_gui(filename, settings)
+}
+
+/// evaluate a python file without the GUI
+fn evaluate_variants(
+    filename: &str,
+    settings: &settings::Settings,
+) -> Result<Vec<python::Variant>, MpError> {
+    Ok(if settings.sandbox {
+        worker::evaluate(filename, vec![], settings)?.evaluation?.variants
+    } else {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        python::init(py, settings)?;
+        python::evaluate(py, filename, &[])?.variants
+    })
+}
+
+/// every variant needs its own file name, or one would overwrite the other
+fn check_file_names(variants: &[python::Variant]) -> Result<(), MpError> {
+    let mut names = HashMap::new();
+    for variant in variants {
+        if let Some(other) = names.insert(variant.file_name(), variant.label()) {
+            return Err(MpError::Other(format!(
+                "variants '{}' and '{}' have the same file name '{}'",
+                other,
+                variant.label(),
+                variant.file_name()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// headless export: write one .kicad_mod file per footprint variant
+fn export(filename: &str, dir: &Path, settings: &settings::Settings) -> Result<(), MpError> {
+    let variants = evaluate_variants(filename, settings)?;
+    check_file_names(&variants)?;
+    for variant in &variants {
+        let path = dir.join(format!("{}.kicad_mod", variant.file_name()));
+        info!("exporting {}", path.display());
+        let mut f = fs::File::create(&path)?;
+        kicad::save(&variant.elements, &mut f)?;
+    }
+    Ok(())
+}
+
+/// headless rendering: write one image per footprint variant, with the
+/// layers and colors of the settings
+fn render_images(
+    filename: &str,
+    dir: &Path,
+    format: &str,
+    back: bool,
+    options: &render::ImageOptions,
+    settings: &settings::Settings,
+) -> Result<(), MpError> {
+    scheme::select(&scheme::load_schemes(), settings.color_scheme.as_ref());
+    let variants = evaluate_variants(filename, settings)?;
+    check_file_names(&variants)?;
+    for variant in &variants {
+        let draw_state = DrawState {
+            bound: element::bound(&variant.elements),
+            elements: variant.elements.clone(),
+            back,
+            layers: settings.layer_views(),
+            ..DrawState::default()
+        };
+        let path = dir.join(format!("{}.{}", variant.file_name(), format));
+        render::export_image(&draw_state, &path, options)?;
+    }
+    Ok(())
+}
+
+/// show one footprint variant: run KLC on it and draw it
+fn show_variant(
+    ui: &gui::GuiData,
+    draw_state: &Arc<Mutex<DrawState>>,
+    settings: &settings::Settings,
+    variant: &python::Variant,
+) -> Result<(), MpError> {
+    let mut draw_state = draw_state.lock().unwrap();
+    draw_state.elements = variant.elements.clone();
+
+    // check KLC and show result in KLC tab
+    let footprint = kicad::to_footprint(&draw_state.elements);
+    // the external checker covers all the native rules, they are only
+    // used when it is not configured or failed
+    let mut issues = if let Some(ref klc_dir) = settings.klc_dir {
+        let output = klc::run_klc(&draw_state, klc_dir, settings);
+        ui.set_klc_text(&output.text());
+        match output.failure {
+            None => output.issues,
+            Some(failure) => {
+                warn!("{}", failure);
+                let mut issues = klc::check(&footprint);
+                issues.push(check::Issue::new(
+                    "KLC",
+                    check::Severity::Error,
+                    failure,
+                    None,
+                ));
+                issues
+            }
+        }
+    } else {
+        ui.set_klc_text("check_kicad_mod.py not configured");
+        klc::check(&footprint)
+    };
+    issues.sort_by(|a, b| b.severity.cmp(&a.severity));
+    ui.set_klc_issues(&issues);
+
+    let mut drc_issues = drc::check(&footprint, &settings.fab_profile());
+    drc_issues.extend(pads::check(&draw_state.elements));
+    drc_issues.sort_by(|a, b| b.severity.cmp(&a.severity));
+    ui.set_drc_issues(&drc_issues);
+
+    // draw on screen
+    draw_state.bound = element::bound(&draw_state.elements);
+    draw_state.bounds = draw_state
+        .elements
+        .iter()
+        .map(|e| e.bounding_box())
+        .collect();
+    ui.set_elements(&draw_state.elements);
+    // the selection handlers of the lists can't lock the draw state while
+    // their lists are refilled here, so reset what they would have reset
+    draw_state.highlight = None;
+    draw_state.hover = None;
+    info!("Bound: {:?}", draw_state.bound);
+    let mut title = format!("madparts (rustic edition) {} : ", VERSION);
+    title.push_str(&draw_state.name());
+    ui.set_title(&title);
+    ui.set_status(&format!("{} ready.", draw_state.name()));
+    ui.show_text_page();
+    ui.draw();
+    Ok(())
+}
+
+fn run_gui(filename: &str, mut settings: settings::Settings) -> Result<(), MpError> {
     let filepath: PathBuf = Path::new(&filename).canonicalize().unwrap();
     info!("Filename: {}", filepath.display());
 
@@ -86,66 +348,67 @@ fn main() -> Result<(), MpError> {
         return Err(err.into());
     }
 
-    let mut ino = match Inotify::init() {
-        Ok(ino) => ino,
-        Err(err) => {
-            error!("Failed to initialize INotify");
-            return Err(err.into());
-        }
-    };
+    let watcher = Arc::new(Mutex::new(watch::Watcher::new()?));
+    watcher.lock().unwrap().set_files(vec![filepath.clone()]);
 
-    let settings = settings::load_settings();
+    if settings.sandbox {
+        info!("evaluating in sandbox");
+    }
 
-    // close_write,moved_to,create indicate the file was possibly messed with
-    let _file_watch = ino.add_watch(
-        &filedir,
-        WatchMask::CREATE | WatchMask::MOVED_TO | WatchMask::CLOSE_WRITE,
-    ).unwrap();
+    let layer_views = settings.layer_views();
+    let draw_state = Arc::new(Mutex::new(DrawState {
+        layers: layer_views.clone(),
+        grid: settings.grid.clone(),
+        ..DrawState::default()
+    }));
 
-    let draw_state = Arc::new(Mutex::new(DrawState::default()));
+    let schemes = scheme::load_schemes();
+    scheme::select(&schemes, settings.color_scheme.as_ref());
+    // only a scheme picked in the menu is saved, an unknown one in the
+    // settings is left alone
+    let initial_scheme = scheme::current_name();
 
-    let ui = gui::make_gui(&filename, draw_state.clone());
+    let ui = gui::make_gui(&filename, draw_state.clone(), schemes);
 
     let update_input = Arc::new(AtomicBool::new(true));
     let update_input_timeout_loop = update_input.clone();
+    let watcher_timeout_loop = watcher.clone();
     gtk::timeout_add(250, move || {
-        let mut buffer = [0; 1024];
-        for event in ino.read_events(&mut buffer).unwrap() {
-            let mut modified = false;
-            if event.name == filepath.file_name() {
-                debug!("modified!");
-                modified = true;
-            }
-            if modified {
-                update_input_timeout_loop.store(true, Ordering::SeqCst);
-                break;
-            }
+        if watcher_timeout_loop.lock().unwrap().poll() {
+            update_input_timeout_loop.store(true, Ordering::SeqCst);
         }
         glib::Continue(true)
     });
 
     ui.show_all();
 
-    let gil = Python::acquire_gil();
-    let py = gil.python();
+    let mut variants: Vec<python::Variant> = vec![];
 
-    let sys = py.import("sys")?;
-    let version: String = sys.get("version")?.extract()?;
-
-    info!("using python: {}", version);
-
-    py.run(PRELUDEPY, None, None)?;
-    // info!("Using prelude: {}", PRELUDEPY);
-    info!("prelude loaded.");
+    let (tx, rx) = mpsc::channel();
+    let mut job: Option<worker::Job> = None;
+    let mut job_id = 0;
 
     loop {
         if ui.want_exit() {
+            if let Some(job) = job {
+                job.cancel();
+            }
+            let draw_state = draw_state.lock().unwrap();
+            let scheme_name = scheme::current_name();
+            let scheme_changed = scheme_name != initial_scheme;
+            if draw_state.layers != layer_views || scheme_changed {
+                settings.set_layer_views(&draw_state.layers);
+                if scheme_changed {
+                    settings.color_scheme = Some(scheme_name);
+                }
+                settings::save_gui_settings(&settings);
+            }
             break;
         }
         if ui.want_save() {
             let filename = {
                 let draw_state = draw_state.lock().unwrap();
-                format!("{}.kicad_mod", draw_state.name())
+                format!("{}.kicad_mod", draw_state.file_name())
             };
             if let Some(filename) = gui::get_export_filename(&ui, filename) {
                 let draw_state = draw_state.lock().unwrap();
@@ -155,78 +418,180 @@ fn main() -> Result<(), MpError> {
                 // handle failure to select filename ?
             }
         }
+        if ui.want_save_image() {
+            let filename = {
+                let draw_state = draw_state.lock().unwrap();
+                format!("{}.png", draw_state.file_name())
+            };
+            if let Some((filename, options)) = gui::get_image_filename(&ui, filename) {
+                let draw_state = draw_state.lock().unwrap();
+                match render::export_image(&draw_state, &filename, &options) {
+                    Ok(()) => ui.set_status(&format!("Exported {}.", filename.display())),
+                    Err(e) => {
+                        error!("{:?}", e);
+                        ui.set_status(&format!("{:?}", e));
+                    }
+                }
+            }
+        }
         gtk::main_iteration();
-        if update_input.compare_and_swap(true, false, Ordering::SeqCst) {
-            ui.set_status("Updating...");
-            let data = fs::read_to_string(&filename).unwrap();
-            ui.set_input_text(&data);
-            debug!("updated");
-            let res = match py.eval(&format!("handle_load_python(\"{}\")", filename), None, None) {
-                Ok(res) => res,
+        let reload = update_input.compare_and_swap(true, false, Ordering::SeqCst);
+        let retune = ui.want_params();
+        if reload || retune {
+            let data = match fs::read_to_string(&filename) {
+                Ok(data) => data,
+                // an editor saving the file can make it briefly missing,
+                // the watcher sees it coming back and triggers a reload
                 Err(e) => {
-                    e.print(py);
+                    warn!("Failed to read {}: {}", filename, e);
+                    ui.set_status(&format!("Failed to read {}: {}", filename, e));
                     continue;
                 }
             };
-            info!("res: {:?}", res);
-            let resl: &PyList = res.extract()?;
-            let mut draw_state = draw_state.lock().unwrap();
-            draw_state.elements.clear();
-            // try to convert python provided elements
-            let mut failed = false;
-            for i in 0..resl.len() {
-                let item = resl.get_item(i as isize);
-                let gen = item.call_method0("generate")?;
-                //info!("gen: {:?}", gen);
-                let genl: &PyList = gen.extract()?;
-                for j in 0..genl.len() {
-                    let item = genl.get_item(j as isize);
-                    info!("item: {:?}", item);
-                    let json: String = item.extract()?;
-                    let x = element::Element::try_from(json)?;
-                    info!("x: '{:?}'", x);
-                    if let element::Element::PythonError(element::PythonError { message }) = x {
-                        let message = message.replace("<string>", &filename);
-                        ui.set_input_text(&message);
-                        ui.show_drawing_page();
-                        failed = true;
-                        break;
-                    }
-                    draw_state.elements.push(x);
-                }
+            if reload {
+                ui.file_changed(&data);
             }
-            if failed {
-                continue;
+            debug!("updated");
+            // a newer evaluation replaces the running one
+            if let Some(job) = job.take() {
+                job.cancel();
             }
-
-            // save to temporary file and run KLC
-            // and show result in KLC tab
-            if let Some(ref klc_dir) = settings.klc_dir {
-                let klc_txt = klc::run_klc(&draw_state, klc_dir)?;
-                ui.set_klc_text(&klc_txt);
-            } else {
-                ui.set_klc_text("KLC not configured");
+            job_id += 1;
+            job = Some(worker::spawn(
+                job_id,
+                &filename,
+                ui.param_overrides(),
+                &settings,
+                tx.clone(),
+            ));
+            ui.set_busy(true);
+        }
+        let mut show = ui.want_variant();
+        // replies to evaluations that were replaced are dropped
+        if let Some(reply) = rx.try_recv().ok().filter(|r| r.id == job_id) {
+            job = None;
+            ui.set_busy(false);
+            // also watch the files the script depends on, even if it failed
+            let mut deps = reply.output.dependencies;
+            if !deps.is_empty() {
+                deps.push(filepath.clone());
+                watcher.lock().unwrap().set_files(deps);
+            }
+            ui.clear_python_error();
+            let evaluation = match reply.output.evaluation {
+                Ok(evaluation) => evaluation,
+                Err(EvalError::Script(e)) => {
+                    error!("{}", e.message);
+                    ui.show_python_error(&e);
+                    ui.show_drawing_page();
+                    continue;
+                }
+                Err(EvalError::Convert(e)) => {
+                    // keep showing the last footprint that was fine
+                    error!("{}", e);
+                    ui.show_error_text(&format!("{}", e));
+                    ui.set_status(
+                        "Invalid element (see the input tab), showing the last valid footprint.",
+                    );
+                    continue;
+                }
+                Err(EvalError::Internal(e)) => {
+                    error!("{:?}", e);
+                    ui.set_status(&format!("{:?}", e));
+                    continue;
+                }
+            };
+            ui.set_params(&evaluation.params);
+            variants = evaluation.variants;
+            let labels = variants.iter().map(|v| v.label()).collect::<Vec<String>>();
+            ui.set_variants(&labels);
+            show = true;
+        }
+        if !show {
+            continue;
+        }
+        if let Some(variant) = variants.get(ui.selected_variant()) {
+            if let Err(e) = show_variant(&ui, &draw_state, &settings, variant) {
+                error!("{:?}", e);
+                ui.set_status(&format!("{:?}", e));
             }
-
-            // draw on screen
-            draw_state.bound = element::bound(&draw_state.elements);
-            info!("Bound: {:?}", draw_state.bound);
-            let mut title = format!("madparts (rustic edition) {} : ", VERSION);
-            title.push_str(&draw_state.name());
-            ui.set_title(&title);
-            ui.set_status(&format!("{} ready.", draw_state.name()));
-            ui.show_text_page();
-            ui.draw();
         }
     }
     Ok(())
 }
 
+mod check;
+mod drc;
+mod editor;
 mod element;
 mod error;
+mod geometry;
+mod grid;
 mod gui;
 mod kicad;
 mod klc;
 mod layers;
+mod measure;
+mod pads;
+mod pyconvert;
+mod python;
+mod render;
+mod sandbox;
+mod scheme;
 mod settings;
 mod util;
+mod watch;
+mod worker;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use element::{Element, Name, Text};
+    use layers::Layer;
+    use python::Variant;
+
+    fn variant(name: &str, params: &str) -> Variant {
+        Variant {
+            params: params.into(),
+            elements: vec![Element::Name(Name {
+                text: Text {
+                    x: 0.0,
+                    y: 0.0,
+                    dy: 1.0,
+                    txt: name.into(),
+                    thickness: 0.15,
+                    shorten: None,
+                    layer: Layer::FFab,
+                },
+            })],
+        }
+    }
+
+    #[test]
+    fn different_file_names() {
+        assert!(check_file_names(&[]).is_ok());
+        assert!(check_file_names(&[variant("R_0603", "")]).is_ok());
+        let variants = [variant("PinHeader_1x02", "n=2"), variant("PinHeader_1x03", "n=3")];
+        assert!(check_file_names(&variants).is_ok());
+    }
+
+    #[test]
+    fn same_file_names() {
+        let variants = [variant("PinHeader", "n=2"), variant("PinHeader", "n=3")];
+        match check_file_names(&variants) {
+            Err(MpError::Other(e)) => assert_eq!(
+                e,
+                "variants 'PinHeader (n=2)' and 'PinHeader (n=3)' have the same \
+                 file name 'PinHeader'"
+            ),
+            r => panic!("{:?}", r),
+        }
+        // names that only differ in what file_name replaces
+        let variants = [variant("a/b", "x=1"), variant("a\\b", "x=2")];
+        assert!(check_file_names(&variants).is_err());
+        let variants = [variant("../x", "x=1"), variant("__/x", "x=2")];
+        assert!(check_file_names(&variants).is_err());
+        let variants = [variant("", "x=1"), variant("NAME", "x=2")];
+        assert!(check_file_names(&variants).is_err());
+    }
+}
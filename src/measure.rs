@@ -0,0 +1,74 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Snapping of the cursor for measuring in the view.
+
+use element::Element;
+use geometry::{Outline, Point};
+use grid::Grid;
+
+/// how close the cursor has to be to snap to a pad, in pixels
+const SNAP_RADIUS: f64 = 8.0;
+
+/// what the cursor snaps to
+#[derive(Debug, Clone, Copy)]
+pub struct Snap {
+    pub grid: bool,
+    pub pad_centers: bool,
+    pub pad_edges: bool,
+}
+
+fn pads(elements: &[Element]) -> Vec<(Point, Outline)> {
+    elements
+        .iter()
+        .filter_map(|e| match *e {
+            Element::Smd(ref s) => Some((Point::new(s.x, s.y), s.outline())),
+            Element::Pad(ref p) => Some((Point::new(p.x, p.y), p.outline())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn closest(p: Point, candidates: &[Point], radius: f64) -> Option<Point> {
+    candidates
+        .iter()
+        .filter(|c| c.distance(&p) <= radius)
+        .fold(None, |best: Option<Point>, c| match best {
+            Some(b) if b.distance(&p) <= c.distance(&p) => Some(b),
+            _ => Some(*c),
+        })
+}
+
+/// snap p to the closest pad center or pad edge nearby, or else to the
+/// visible grid; pixel is the size of a pixel in mm
+pub fn snap(p: Point, elements: &[Element], snap: &Snap, grid: &Grid, pixel: f64) -> Point {
+    let radius = SNAP_RADIUS * pixel;
+    let pads = pads(elements);
+    if snap.pad_centers {
+        let centers = pads.iter().map(|&(c, _)| c).collect::<Vec<Point>>();
+        if let Some(c) = closest(p, &centers, radius) {
+            return c;
+        }
+    }
+    if snap.pad_edges {
+        let edges = pads.iter()
+            .map(|&(_, ref o)| o.closest_edge_point(p))
+            .collect::<Vec<Point>>();
+        if let Some(e) = closest(p, &edges, radius) {
+            return e;
+        }
+    }
+    if snap.grid {
+        return grid.snap(p, pixel);
+    }
+    p
+}
+
+/// the ruler readout for a measurement from a to b
+pub fn describe(a: Point, b: Point) -> String {
+    format!(
+        "dx {:.3} dy {:.3} distance {:.3} mm",
+        b.x - a.x,
+        b.y - a.y,
+        a.distance(&b)
+    )
+}
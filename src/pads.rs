@@ -0,0 +1,242 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Sanity checks on the pad names: duplicates, gaps in the numbering,
+// empty names and the position of pin 1.
+
+use std::collections::{BTreeMap, HashSet};
+
+use check::{ElementRef, Issue, Severity};
+use element::{Element, Rect};
+use geometry::Point;
+use layers::Layer;
+
+/// what the checks need to know of a Smd or Pad
+struct PadInfo {
+    name: String,
+    center: Point,
+    allow_duplicate: bool,
+    /// non plated holes usually have no name
+    needs_name: bool,
+    element: ElementRef,
+}
+
+fn pad_infos(elements: &[Element]) -> Vec<PadInfo> {
+    let mut pads = vec![];
+    for e in elements {
+        match *e {
+            Element::Smd(ref s) => pads.push(PadInfo {
+                name: s.name.clone(),
+                center: Point::new(s.x, s.y),
+                allow_duplicate: s.allow_duplicate,
+                needs_name: true,
+                element: ElementRef::smd(s),
+            }),
+            Element::Pad(ref p) => pads.push(PadInfo {
+                name: p.name.clone(),
+                center: Point::new(p.x, p.y),
+                allow_duplicate: p.allow_duplicate,
+                needs_name: p.plated,
+                element: ElementRef::pad(p),
+            }),
+            _ => (),
+        }
+    }
+    pads
+}
+
+fn check_names(issues: &mut Vec<Issue>, pads: &[PadInfo]) {
+    let mut by_name: BTreeMap<&str, Vec<&PadInfo>> = BTreeMap::new();
+    for pad in pads {
+        if pad.name.trim().is_empty() {
+            if pad.needs_name {
+                issues.push(Issue::new(
+                    "pad-name",
+                    Severity::Error,
+                    "pad has no name".into(),
+                    Some(pad.element.clone()),
+                ));
+            }
+            continue;
+        }
+        by_name.entry(&pad.name).or_insert_with(Vec::new).push(pad);
+    }
+    for (name, same) in &by_name {
+        if same.len() > 1 && !same.iter().all(|p| p.allow_duplicate) {
+            issues.push(Issue::new(
+                "pad-duplicate",
+                Severity::Error,
+                format!(
+                    "pad name {} is used {} times, set allow_duplicate on the pads \
+                     if they are meant to share it",
+                    name,
+                    same.len()
+                ),
+                Some(same[1].element.clone()),
+            ));
+        }
+    }
+    // numeric names should form a 1..N sequence
+    let numbers = by_name
+        .keys()
+        .filter_map(|n| n.parse::<u32>().ok())
+        .collect::<HashSet<u32>>();
+    let max = numbers.iter().cloned().max().unwrap_or(0);
+    // a number far above the pad count is a typo or a numbering of its
+    // own, listing the gap below it would not help
+    if let (Some(end), true) = (max.checked_add(1), max as usize <= 2 * numbers.len()) {
+        let missing = (1..end)
+            .filter(|n| !numbers.contains(n))
+            .map(|n| n.to_string())
+            .collect::<Vec<String>>();
+        if !missing.is_empty() {
+            issues.push(Issue::new(
+                "pad-numbering",
+                Severity::Warning,
+                format!("pad numbers {} are missing", missing.join(", ")),
+                None,
+            ));
+        }
+    }
+}
+
+/// pin 1 has to be at the chamfered corner of the fab outline
+fn check_pin1(issues: &mut Vec<Issue>, elements: &[Element], pads: &[PadInfo]) {
+    let fab: Vec<&Rect> = elements
+        .iter()
+        .filter_map(|e| match *e {
+            Element::Rect(ref r) if r.layer == Layer::FFab && r.corner.is_some() => Some(r),
+            _ => None,
+        })
+        .collect();
+    if fab.len() != 1 {
+        return;
+    }
+    // the chamfer is in the top-left corner, before rotation
+    let corners = fab[0].corners();
+    for pad in pads.iter().filter(|p| p.name == "1") {
+        let distances = corners
+            .iter()
+            .map(|c| c.distance(&pad.center))
+            .collect::<Vec<f64>>();
+        if distances[1..].iter().any(|&d| d < distances[0]) {
+            issues.push(Issue::new(
+                "pin1",
+                Severity::Warning,
+                "pin 1 is not at the chamfered corner of the fab outline".into(),
+                Some(pad.element.clone()),
+            ));
+        }
+    }
+}
+
+/// check the pad names of a footprint
+pub fn check(elements: &[Element]) -> Vec<Issue> {
+    let mut issues = vec![];
+    let pads = pad_infos(elements);
+    check_names(&mut issues, &pads);
+    check_pin1(&mut issues, elements, &pads);
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use element::{Pad, Smd, SmdShape};
+
+    fn smd(name: &str, x: f64) -> Smd {
+        Smd {
+            name: name.into(),
+            x,
+            y: 0.0,
+            dx: 0.5,
+            dy: 0.5,
+            layers: vec![Layer::FCu, Layer::FPaste, Layer::FMask],
+            shape: SmdShape::Rect,
+            rot: 0.0,
+            allow_duplicate: false,
+        }
+    }
+
+    fn hole(name: &str, plated: bool) -> Element {
+        Element::Pad(Pad {
+            name: name.into(),
+            x: 5.0,
+            y: 0.0,
+            dx: 1.0,
+            dy: 1.0,
+            drill: 0.5,
+            layers: vec![Layer::Cu, Layer::Mask],
+            plated,
+            rot: 0.0,
+            allow_duplicate: false,
+        })
+    }
+
+    fn pads(names: &[&str]) -> Vec<Element> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, n)| Element::Smd(smd(n, i as f64)))
+            .collect()
+    }
+
+    fn rules(issues: &[Issue]) -> Vec<&str> {
+        issues.iter().map(|i| i.rule.as_str()).collect()
+    }
+
+    #[test]
+    fn good_names() {
+        assert!(check(&pads(&["1", "2", "3"])).is_empty());
+        assert!(check(&pads(&["A1", "B1"])).is_empty());
+    }
+
+    #[test]
+    fn duplicates() {
+        let issues = check(&pads(&["1", "2", "2"]));
+        assert_eq!(rules(&issues), vec!["pad-duplicate"]);
+        assert!(issues[0].message.contains("used 2 times"));
+    }
+
+    #[test]
+    fn intentional_duplicates() {
+        let mut ep = smd("3", 0.0);
+        ep.allow_duplicate = true;
+        let mut ep2 = ep.clone();
+        ep2.x = 1.0;
+        let mut elements = pads(&["1", "2"]);
+        elements.push(Element::Smd(ep));
+        elements.push(Element::Smd(ep2.clone()));
+        assert!(check(&elements).is_empty());
+        // all of them have to allow it
+        ep2.allow_duplicate = false;
+        elements.push(Element::Smd(ep2));
+        assert_eq!(rules(&check(&elements)), vec!["pad-duplicate"]);
+    }
+
+    #[test]
+    fn gaps() {
+        let issues = check(&pads(&["1", "2", "5"]));
+        assert_eq!(rules(&issues), vec!["pad-numbering"]);
+        assert_eq!(issues[0].message, "pad numbers 3, 4 are missing");
+    }
+
+    #[test]
+    fn empty_names() {
+        let issues = check(&pads(&["1", " "]));
+        assert_eq!(rules(&issues), vec!["pad-name"]);
+        // a non plated hole needs no name
+        let mut elements = pads(&["1"]);
+        elements.push(hole("", false));
+        assert!(check(&elements).is_empty());
+        elements.push(hole("", true));
+        assert_eq!(rules(&check(&elements)), vec!["pad-name"]);
+    }
+
+    #[test]
+    fn huge_numbers() {
+        // no overflow, and no walk over all the numbers below it
+        assert!(check(&pads(&["1", "4294967295"])).is_empty());
+        assert!(check(&pads(&["1", "2", "99999999"])).is_empty());
+        assert!(check(&pads(&["4294967296"])).is_empty());
+    }
+}
@@ -1,10 +1,11 @@
 import copy
+import math
+import os
 
 """Base class for footprint elements."""
 class Element:
     """Base class for footprint elements.
     
-    Provides code for conversion to json.
     Normally not used directly by end-users.
     """
     
@@ -12,8 +13,79 @@ class Element:
         self.t = self.__class__.__name__
         
     def generate(self):
-        import json
-        return [json.dumps(self.__dict__)]
+        """The elements madparts converts this element into"""
+        return [self]
+
+    def transformed(self, t):
+        """Return a copy of this Element with Transform t applied"""
+        return copy.copy(self)
+
+class Transform:
+    """Affine 2D transformation, the same as the one used inside madparts.
+
+    x points right, y points down and positive angles rotate
+    counter-clockwise as seen on screen, like in Kicad.
+    Use the static constructors and combine with then().
+    """
+
+    def __init__(self, xx=1.0, yx=0.0, xy=0.0, yy=1.0, x0=0.0, y0=0.0):
+        (self.xx, self.yx, self.xy, self.yy, self.x0, self.y0) = (xx, yx, xy, yy, x0, y0)
+
+    @staticmethod
+    def translation(dx, dy):
+        return Transform(x0=dx, y0=dy)
+
+    @staticmethod
+    def rotation(deg):
+        r = math.radians(deg)
+        (s, c) = (math.sin(r), math.cos(r))
+        return Transform(xx=c, yx=-s, xy=s, yy=c)
+
+    @staticmethod
+    def mirroring_x():
+        """Mirror around the y axis, e.g. x becomes -x"""
+        return Transform(xx=-1.0)
+
+    @staticmethod
+    def scaling(s):
+        return Transform(xx=s, yy=s)
+
+    def then(self, o):
+        """Combined transform: first apply self, then o"""
+        return Transform(
+            o.xx * self.xx + o.xy * self.yx,
+            o.yx * self.xx + o.yy * self.yx,
+            o.xx * self.xy + o.xy * self.yy,
+            o.yx * self.xy + o.yy * self.yy,
+            o.xx * self.x0 + o.xy * self.y0 + o.x0,
+            o.yx * self.x0 + o.yy * self.y0 + o.y0)
+
+    def apply(self, x, y):
+        return (self.xx * x + self.xy * y + self.x0,
+                self.yx * x + self.yy * y + self.y0)
+
+    def is_mirrored(self):
+        return self.xx * self.yy - self.xy * self.yx < 0
+
+    def scale_factor(self):
+        return math.sqrt(abs(self.xx * self.yy - self.xy * self.yx))
+
+    def apply_rotation(self, rot):
+        """New rotation of an Element with rotation rot"""
+        if self.is_mirrored():
+            a = math.degrees(math.atan2(self.yx, -self.xx))
+            return (a - rot) % 360.0
+        a = math.degrees(math.atan2(-self.yx, self.xx))
+        return (a + rot) % 360.0
+
+def _transformed_box(e, t):
+    """Transform the common x, y, dx, dy, rot attributes of a copy of e"""
+    n = copy.copy(e)
+    (n.x, n.y) = t.apply(e.x, e.y)
+    n.dx = e.dx * t.scale_factor()
+    n.dy = e.dy * t.scale_factor()
+    n.rot = t.apply_rotation(e.rot)
+    return n
 
 class Rect(Element):
     """Rectangular shaped Element
@@ -31,6 +103,7 @@ class Rect(Element):
         w  (float): line width
         filled (boolean): if the rectangular should be filled (default False)
         layer (string): Kicad layer to use (default "F.SilkS")
+        rot (float): rotation in degrees (default 0.0)
     """
     
     def __init__(self, dx, dy, w=0.1):
@@ -43,6 +116,17 @@ class Rect(Element):
         self.w = w
         self.filled = False
         self.layer = "F.SilkS"
+        self.rot = 0.0
+
+    def transformed(self, t):
+        n = _transformed_box(self, t)
+        if getattr(self, 'corner', None) is not None:
+            n.corner = self.corner * t.scale_factor()
+            if t.is_mirrored():
+                # the chamfer is always in the top-left corner
+                (n.dx, n.dy) = (n.dy, n.dx)
+                n.rot = (n.rot - 90.0) % 360.0
+        return n
 
 class FCrtYd(Rect):
     """
@@ -108,6 +192,55 @@ class Line(Element):
         self.w = w
         self.layer = "F.SilkS"
 
+    def transformed(self, t):
+        n = copy.copy(self)
+        (n.x1, n.y1) = t.apply(self.x1, self.y1)
+        (n.x2, n.y2) = t.apply(self.x2, self.y2)
+        return n
+
+class Dimension(Element):
+    """
+    Dimension annotation: the distance between two points in mm, with
+    extension lines and arrows
+
+    Keyword arguments:
+        p1 (float,float): point 1
+        p2 (float,float): point 2
+        offset (float): distance of the dimension line from the points,
+            positive is to the left of p1 to p2 as seen on screen
+        layer (string): Kicad layer to use (default "Dwgs.User")
+
+    Attributes:
+        x1 (float): x position of point 1
+        y1 (float): y position of point 1
+        x2 (float): x position of point 2
+        y2 (float): y position of point 2
+        offset (float): distance of the dimension line from the points
+        w  (float): line width (default 0.1)
+        dy (float): size of the text (default 1.0)
+        layer (string): Kicad layer to use
+
+    """
+
+    def __init__(self, p1, p2, offset, layer="Dwgs.User"):
+        Element.__init__(self)
+        (self.x1, self.y1) = p1
+        (self.x2, self.y2) = p2
+        self.offset = offset
+        self.w = 0.1
+        self.dy = 1.0
+        self.layer = layer
+
+    def transformed(self, t):
+        """The offset changes sign when mirrored, as left becomes right"""
+        n = copy.copy(self)
+        (n.x1, n.y1) = t.apply(self.x1, self.y1)
+        (n.x2, n.y2) = t.apply(self.x2, self.y2)
+        n.offset = self.offset * t.scale_factor()
+        if t.is_mirrored():
+            n.offset = -n.offset
+        return n
+
 class Text(Element):
     """
     Text Element
@@ -136,6 +269,12 @@ class Text(Element):
         self.thickness = th
         self.layer = "F.SilkS"
 
+    def transformed(self, t):
+        """Text is moved but stays upright and keeps its size"""
+        n = copy.copy(self)
+        (n.x, n.y) = t.apply(self.x, self.y)
+        return n
+
 class Reference(Text):
     """
     Text Element to be used for the kicad Reference field.
@@ -180,6 +319,9 @@ class Smd(Element):
         dx (float): x size
         dy (float): y size
         layers ([string]): Kicad layers to use (default  ["F.Cu", "F.Paste", "F.Mask"])
+        rot (float): rotation in degrees (default 0.0)
+        allow_duplicate (boolean): the name is meant to be shared with
+          other pads, e.g. an exposed pad and its vias (default False)
     """
     
     def __init__(self, name, s, p=(0,0)):
@@ -189,6 +331,11 @@ class Smd(Element):
         (self.x, self.y) = p
         self.layers = ["F.Cu", "F.Paste", "F.Mask"]
         self.shape = "rect"
+        self.rot = 0.0
+        self.allow_duplicate = False
+
+    def transformed(self, t):
+        return _transformed_box(self, t)
 
     def at(self, name, x, y):
         """Create a copy of this SMD Pad with a new name and location"""
@@ -218,6 +365,9 @@ class Pad(Element):
         dx (float): x size
         dy (float): y size
         layers ([string]): Kicad layers to use (default  ["*.Cu", "*.Mask"])
+        rot (float): rotation in degrees (default 0.0)
+        allow_duplicate (boolean): the name is meant to be shared with
+          other pads, e.g. an exposed pad and its vias (default False)
     """
       
     def __init__(self, name, s, d, p=(0,0)):
@@ -229,6 +379,13 @@ class Pad(Element):
         self.drill = d
         self.layers = ["*.Cu", "*.Mask"]
         self.plated = True
+        self.rot = 0.0
+        self.allow_duplicate = False
+
+    def transformed(self, t):
+        n = _transformed_box(self, t)
+        n.drill = self.drill * t.scale_factor()
+        return n
 
     def at(self, name, x, y):
         """Create a copy of this PTH Pad with a new name and location"""
@@ -256,6 +413,33 @@ class Model(Element):
         Element.__init__(self)
         self.filename = filename
 
+class Desc(Element):
+    """
+    Description of the footprint, for the Kicad library
+
+    Keyword arguments:
+        txt (string): description, e.g. a link to the datasheet
+    """
+
+    def __init__(self, txt):
+        Element.__init__(self)
+        self.txt = txt
+
+class Tags(Element):
+    """
+    Keywords of the footprint, for searching the Kicad library
+
+    Keyword arguments:
+        tags (string or list of strings): keywords, a string is split on
+            spaces
+    """
+
+    def __init__(self, tags):
+        Element.__init__(self)
+        if isinstance(tags, str):
+            tags = tags.split()
+        self.txt = " ".join(tags)
+
 # Arc
 
 # Circle
@@ -301,6 +485,48 @@ def single(pad, dy, n, name=None):
         l.append(pad.at(name2, 0, -dyn/2 + dy/2 + dy*i))
     return l
 
+def transform(elements, t):
+    """Return copies of all elements with Transform t applied
+    """
+
+    if not isinstance(elements, list):
+        elements = [elements]
+    return [e.transformed(t) for e in flatten(elements)]
+
+def translate(elements, dx, dy):
+    """Move a group of elements over dx, dy
+    """
+
+    return transform(elements, Transform.translation(dx, dy))
+
+def rotate(elements, deg):
+    """Rotate a group of elements counter-clockwise around the origin
+    """
+
+    return transform(elements, Transform.rotation(deg))
+
+def mirror_x(elements):
+    """Mirror a group of elements around the y axis
+    """
+
+    return transform(elements, Transform.mirroring_x())
+
+def variants(table):
+    """Decorator turning footprint() in a family of footprints
+
+    Every entry of the table is either a dict of keyword arguments
+    or a single argument for footprint(), e.g.:
+
+        @variants([2, 4, 6, 8])
+        def footprint(n):
+            ...
+    """
+
+    def decorate(f):
+        f.variants = table
+        return f
+    return decorate
+
 ### internal functions and classes
 
 class PythonError(Element):
@@ -311,6 +537,9 @@ class PythonError(Element):
     def __init__(self, message):
         Element.__init__(self)
         self.message = message
+        self.exception = message
+        # (filename, line, function) tuples, innermost last
+        self.frames = []
 
 def flatten(l):
     if l == []:
@@ -319,13 +548,166 @@ def flatten(l):
         return flatten(l[0]) + flatten(l[1:])
     return l[:1] + flatten(l[1:])
 
-def handle_load_python(filename):
+def describe_params(params):
+    if isinstance(params, dict):
+        return ", ".join("%s=%s" % (k, params[k]) for k in sorted(params))
+    return str(params)
+
+def load_params(g, overrides):
+    """Describe the tunable parameters declared in PARAMS and
+    determine their values, applying the overrides from the GUI.
+
+    A script declares its parameters as
+
+        PARAMS = {"pitch": (2.54, 1.0, 5.0), "n": (4, 1, 40)}
+
+    with a (default, min, max) tuple per name; an int default makes
+    an integer parameter. Every value is passed to footprint(),
+    footprints() or the variants as a keyword argument, so they must
+    accept all of them, e.g. def footprint(pitch, n).
+    """
+
+    info = []
+    values = {}
+    for (name, (default, lo, hi)) in g.get('PARAMS', {}).items():
+        info.append((name, default, lo, hi, isinstance(default, int)))
+        values[name] = type(default)(overrides.get(name, default))
+    return (info, values)
+
+def load_variants(g, values):
+    """Collect (params, elements) pairs from either footprints()
+    or a (possibly decorated) footprint().
+
+    The PARAMS values are passed as keyword arguments.
+    """
+
+    if 'footprints' in g:
+        return [(describe_params(p), flatten(list(e))) for (p, e) in g['footprints'](**values)]
+    f = g['footprint']
+    if hasattr(f, 'variants'):
+        l = []
+        for p in f.variants:
+            if isinstance(p, dict):
+                kwargs = dict(values)
+                kwargs.update(p)
+                e = f(**kwargs)
+            else:
+                e = f(p, **values)
+            l.append((describe_params(p), flatten(e)))
+        return l
+    return [("", flatten(f(**values)))]
+
+library_paths = []
+script_dirs = []
+dependencies = []
+
+def set_library_paths(paths):
+    """Make the library paths from the settings importable
+    """
+
+    import sys
+    global library_paths
+    library_paths = [os.path.abspath(p) for p in paths]
+    for p in reversed(library_paths):
+        if p not in sys.path:
+            sys.path.insert(0, p)
+
+def user_modules():
+    """(name, filename) of the loaded python modules next to the
+    footprint scripts or in the library paths; installed packages,
+    wherever they are, and C extensions are not watched
+    """
+
+    import sys
+    roots = library_paths + script_dirs
+    l = []
+    for (name, m) in list(sys.modules.items()):
+        f = getattr(m, '__file__', None)
+        if not f or not f.endswith('.py'):
+            continue
+        f = os.path.abspath(f)
+        if any(f.startswith(p + os.sep) for p in roots):
+            l.append((name, f))
+    return l
+
+def run_script(filename):
+    """Run a footprint script in a fresh namespace containing only the
+    prelude and return that namespace.
+    """
+
+    import sys
+    script_dir = os.path.dirname(os.path.abspath(filename))
+    if script_dir not in sys.path:
+        sys.path.insert(0, script_dir)
+    if script_dir not in script_dirs:
+        script_dirs.append(script_dir)
+    g = dict((k, v) for (k, v) in globals().items() if not k.startswith('__'))
+    g['__name__'] = '__footprint__'
+    g['__file__'] = filename
+    with open(filename) as f:
+        code = compile(f.read(), filename, 'exec')
+    exec(code, g)
+    return g
+
+def tracking_open(opened):
+    """open() replacement recording the files read by the script"""
+
+    import builtins
+    real_open = builtins.open
+    def do_open(file, mode='r', *args, **kwargs):
+        if isinstance(file, str) and 'r' in mode:
+            opened.append(os.path.abspath(file))
+        return real_open(file, mode, *args, **kwargs)
+    return do_open
+
+def python_error():
+    """PythonError for the exception being handled.
+
+    The frames of the prelude itself are left out; the prelude runs
+    as '<string>', the scripts and their modules have their own
+    filename.
+    """
+    import sys, traceback
+    exc_type, exc_value, exc_traceback = sys.exc_info()
+    frames = [f for f in traceback.extract_tb(exc_traceback) if f[0] != '<string>']
+    exception = "".join(traceback.format_exception_only(exc_type, exc_value))
+    e = PythonError("Traceback (most recent call last):\n"
+                    + "".join(traceback.format_list(frames)) + exception)
+    e.exception = exception
+    e.frames = [(f[0], f[1], f[2]) for f in frames]
+    # a syntax error has no frame of its own
+    if isinstance(exc_value, SyntaxError) and exc_value.filename and exc_value.lineno:
+        e.frames.append((exc_value.filename, exc_value.lineno, '<module>'))
+    return e
+
+def handle_load_python(filename, overrides={}):
+    """Evaluate a footprint script; the files it depends on are
+    left in dependencies.
+    """
+
+    import builtins
+    global dependencies
+    opened = [os.path.abspath(filename)]
+    real_open = builtins.open
+    builtins.open = tracking_open(opened)
     try:
-        exec(open(filename).read(), globals(), globals())
-        return flatten(footprint())
+        g = run_script(filename)
+        (info, values) = load_params(g, overrides)
+        return (info, load_variants(g, values))
     except:
-        import sys, traceback
-        exc_type, exc_value, exc_traceback = sys.exc_info()
-        message = "".join(traceback.format_exception(exc_type, exc_value, exc_traceback))
-        e = PythonError(message)
-        return [e]
+        return ([], [("", [python_error()])])
+    finally:
+        builtins.open = real_open
+        dependencies = sorted(set(opened + [f for (_, f) in user_modules()]))
+
+def register_module():
+    """Make the prelude importable as the madparts module, so helper
+    modules can do 'from madparts import *'.
+    """
+
+    import sys, types
+    m = types.ModuleType("madparts")
+    m.__dict__.update((k, v) for (k, v) in globals().items() if not k.startswith('__'))
+    sys.modules['madparts'] = m
+
+register_module()
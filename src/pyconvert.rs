@@ -0,0 +1,277 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Conversion of the python element objects created by the footprint
+// scripts into their rust counterparts, directly via pyo3.
+
+use std::fmt;
+use std::str::FromStr;
+
+use pyo3::{FromPyObject, ObjectProtocol, PyObjectRef};
+
+use element::*;
+use layers::{Layer, FOOTPRINT_LAYERS};
+
+/// a python element object could not be converted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvertError {
+    /// position of the element in the list returned by the script
+    pub index: usize,
+    /// python class of the element
+    pub class: String,
+    pub attribute: String,
+    /// python repr of the offending value, None when the attribute is missing
+    pub value: Option<String>,
+    pub expected: String,
+    /// the accepted values, for attributes that take one of a fixed set
+    pub allowed: Vec<String>,
+}
+
+impl ConvertError {
+    fn allowing<T: ToString>(mut self, allowed: &[T]) -> ConvertError {
+        self.allowed = allowed.iter().map(|a| a.to_string()).collect();
+        self
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "element {} ({}): ", self.index, self.class)?;
+        match self.value {
+            Some(ref value) => write!(
+                f,
+                "{}: expected {}, got {}",
+                self.attribute, self.expected, value
+            )?,
+            None => write!(
+                f,
+                "{}: attribute is missing, expected {}",
+                self.attribute, self.expected
+            )?,
+        }
+        if !self.allowed.is_empty() {
+            write!(f, "\nallowed values: {}", self.allowed.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn repr(obj: &PyObjectRef) -> String {
+    match obj.repr() {
+        Ok(s) => s.to_string_lossy().into_owned(),
+        Err(_) => "<unprintable>".into(),
+    }
+}
+
+/// python element object together with its class name for error reporting
+struct Obj<'a> {
+    obj: &'a PyObjectRef,
+    class: String,
+}
+
+impl<'a> Obj<'a> {
+    fn new(obj: &'a PyObjectRef) -> Obj<'a> {
+        Obj {
+            obj,
+            class: obj.get_type().name().into_owned(),
+        }
+    }
+
+    fn error(&self, attribute: &str, value: Option<&PyObjectRef>, expected: &str) -> ConvertError {
+        ConvertError {
+            index: 0,
+            class: self.class.clone(),
+            attribute: attribute.into(),
+            value: value.map(repr),
+            expected: expected.into(),
+            allowed: vec![],
+        }
+    }
+
+    fn attr(&self, name: &str, expected: &str) -> Result<&'a PyObjectRef, ConvertError> {
+        self.obj
+            .getattr(name)
+            .map_err(|_| self.error(name, None, expected))
+    }
+
+    /// None when the attribute is not set or is None
+    fn opt_attr(&self, name: &str) -> Option<&'a PyObjectRef> {
+        match self.obj.getattr(name) {
+            Ok(v) if !v.is_none() => Some(v),
+            _ => None,
+        }
+    }
+
+    fn get<T: FromPyObject<'a>>(&self, name: &str, expected: &str) -> Result<T, ConvertError> {
+        let v = self.attr(name, expected)?;
+        v.extract().map_err(|_| self.error(name, Some(v), expected))
+    }
+
+    fn number(&self, name: &str) -> Result<f64, ConvertError> {
+        self.get(name, "a number")
+    }
+
+    fn opt_number(&self, name: &str) -> Result<Option<f64>, ConvertError> {
+        match self.opt_attr(name) {
+            Some(v) => v.extract()
+                .map(Some)
+                .map_err(|_| self.error(name, Some(v), "a number or None")),
+            None => Ok(None),
+        }
+    }
+
+    fn string(&self, name: &str) -> Result<String, ConvertError> {
+        // names are often given as numbers
+        let v = self.attr(name, "a string")?;
+        match v.extract::<String>() {
+            Ok(s) => Ok(s),
+            Err(_) => v.str()
+                .map(|s| s.to_string_lossy().into_owned())
+                .map_err(|_| self.error(name, Some(v), "a string")),
+        }
+    }
+
+    fn boolean(&self, name: &str) -> Result<bool, ConvertError> {
+        let v = self.attr(name, "True or False")?;
+        v.is_true().map_err(|_| self.error(name, Some(v), "True or False"))
+    }
+
+    fn layer_from(&self, name: &str, v: &PyObjectRef) -> Result<Layer, ConvertError> {
+        v.extract::<String>()
+            .ok()
+            .and_then(|s| Layer::from_str(&s).ok())
+            .ok_or_else(|| self.error(name, Some(v), "a layer name").allowing(FOOTPRINT_LAYERS))
+    }
+
+    fn layer(&self, name: &str) -> Result<Layer, ConvertError> {
+        let v = self.attr(name, "a layer name")?;
+        self.layer_from(name, v)
+    }
+
+    fn layers(&self, name: &str) -> Result<Vec<Layer>, ConvertError> {
+        let v = self.attr(name, "a list of layer names")?;
+        // a single string is iterable as well, but is not a list of layers
+        if v.extract::<String>().is_ok() {
+            return Err(self.error(name, Some(v), "a list of layer names"));
+        }
+        let iter = v.iter()
+            .map_err(|_| self.error(name, Some(v), "a list of layer names"))?;
+        let mut layers = vec![];
+        for l in iter {
+            let l = l.map_err(|_| self.error(name, Some(v), "a list of layer names"))?;
+            layers.push(self.layer_from(name, l)?);
+        }
+        Ok(layers)
+    }
+
+    fn text(&self) -> Result<Text, ConvertError> {
+        Ok(Text {
+            x: self.number("x")?,
+            y: self.number("y")?,
+            dy: self.number("dy")?,
+            txt: self.string("txt")?,
+            thickness: self.number("thickness")?,
+            shorten: None,
+            layer: self.layer("layer")?,
+        })
+    }
+}
+
+/// convert one python element object; the index of the error is
+/// left to the caller
+pub fn element(obj: &PyObjectRef) -> Result<Element, ConvertError> {
+    let o = Obj::new(obj);
+    let t: String = o.get("t", "an element type")?;
+    match t.as_str() {
+        "Rect" | "FFab" | "FCrtYd" | "FPaste" | "FMask" => Ok(Element::Rect(Rect {
+            x: o.number("x")?,
+            y: o.number("y")?,
+            dx: o.number("dx")?,
+            dy: o.number("dy")?,
+            w: o.number("w")?,
+            filled: o.boolean("filled")?,
+            layer: o.layer("layer")?,
+            corner: o.opt_number("corner")?,
+            rot: o.opt_number("rot")?.unwrap_or(0.0),
+        })),
+        "Line" => Ok(Element::Line(Line {
+            x1: o.number("x1")?,
+            y1: o.number("y1")?,
+            x2: o.number("x2")?,
+            y2: o.number("y2")?,
+            w: o.number("w")?,
+            layer: o.layer("layer")?,
+        })),
+        "Dimension" => Ok(Element::Dimension(Dimension {
+            x1: o.number("x1")?,
+            y1: o.number("y1")?,
+            x2: o.number("x2")?,
+            y2: o.number("y2")?,
+            offset: o.number("offset")?,
+            w: o.number("w")?,
+            dy: o.number("dy")?,
+            layer: o.layer("layer")?,
+        })),
+        "Name" => {
+            let mut text = o.text()?;
+            text.shorten = Some(true);
+            Ok(Element::Name(Name { text }))
+        }
+        "Reference" => Ok(Element::Reference(Reference { text: o.text()? })),
+        "Desc" => Ok(Element::Desc(Desc {
+            txt: o.string("txt")?,
+        })),
+        "Tags" => Ok(Element::Tags(Tags {
+            txt: o.string("txt")?,
+        })),
+        "Pad" | "Hole" => Ok(Element::Pad(Pad {
+            name: o.string("name")?,
+            x: o.number("x")?,
+            y: o.number("y")?,
+            dx: o.number("dx")?,
+            dy: o.number("dy")?,
+            drill: o.number("drill")?,
+            layers: o.layers("layers")?,
+            plated: o.boolean("plated")?,
+            rot: o.opt_number("rot")?.unwrap_or(0.0),
+            allow_duplicate: o.boolean("allow_duplicate")?,
+        })),
+        "Smd" => {
+            let shape = o.attr("shape", "'rect' or 'circle'")?;
+            Ok(Element::Smd(Smd {
+                name: o.string("name")?,
+                x: o.number("x")?,
+                y: o.number("y")?,
+                dx: o.number("dx")?,
+                dy: o.number("dy")?,
+                layers: o.layers("layers")?,
+                shape: shape
+                    .extract::<String>()
+                    .ok()
+                    .and_then(|s| SmdShape::from_str(&s).ok())
+                    .ok_or_else(|| {
+                        o.error("shape", Some(shape), "'rect' or 'circle'")
+                            .allowing(&["rect", "circle"])
+                    })?,
+                rot: o.opt_number("rot")?.unwrap_or(0.0),
+                allow_duplicate: o.boolean("allow_duplicate")?,
+            }))
+        }
+        "PythonError" => {
+            let frames: Vec<(String, usize, String)> =
+                o.get("frames", "a list of (filename, line, function)")?;
+            Ok(Element::PythonError(PythonError {
+                message: o.string("message")?,
+                exception: o.string("exception")?,
+                frames: frames
+                    .into_iter()
+                    .map(|(filename, line, function)| Frame {
+                        filename,
+                        line,
+                        function,
+                    })
+                    .collect(),
+            }))
+        }
+        _ => Err(o.error("t", Some(o.attr("t", "an element type")?), "an element type")),
+    }
+}
@@ -0,0 +1,267 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+use std::path::PathBuf;
+
+use pyo3::{ObjectProtocol, PyDict, PyList, PyTuple, Python};
+
+use element::{self, Element, PythonError};
+use error::MpError;
+use pyconvert::{self, ConvertError};
+use settings::Settings;
+
+const PRELUDEPY: &'static str = include_str!("prelude.py");
+
+/// one footprint of a (possibly parametric) footprint script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variant {
+    /// description of the parameters used, empty for plain scripts
+    pub params: String,
+    pub elements: Vec<Element>,
+}
+
+impl Variant {
+    pub fn name(&self) -> String {
+        element::footprint_name(&self.elements)
+    }
+
+    pub fn file_name(&self) -> String {
+        element::file_name(&self.elements)
+    }
+
+    /// label used to pick the variant in the GUI
+    pub fn label(&self) -> String {
+        if self.params.is_empty() {
+            self.name()
+        } else {
+            format!("{} ({})", self.name(), self.params)
+        }
+    }
+}
+
+/// tunable parameter declared in the PARAMS dict of a script
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: String,
+    /// default value as declared in the script
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+    pub integer: bool,
+}
+
+/// result of running a footprint script
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Evaluation {
+    pub params: Vec<Param>,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum EvalError {
+    /// the footprint script itself failed
+    Script(PythonError),
+    /// the script returned an element that can't be used
+    Convert(ConvertError),
+    Internal(MpError),
+}
+
+impl From<MpError> for EvalError {
+    fn from(e: MpError) -> EvalError {
+        EvalError::Internal(e)
+    }
+}
+
+impl From<::pyo3::PyErr> for EvalError {
+    fn from(e: ::pyo3::PyErr) -> EvalError {
+        EvalError::Internal(e.into())
+    }
+}
+
+impl From<::pyo3::PyDowncastError> for EvalError {
+    fn from(e: ::pyo3::PyDowncastError) -> EvalError {
+        EvalError::Internal(e.into())
+    }
+}
+
+impl From<EvalError> for MpError {
+    fn from(e: EvalError) -> MpError {
+        match e {
+            EvalError::Script(e) => MpError::Python(e.message),
+            EvalError::Convert(e) => MpError::Python(format!("{}", e)),
+            EvalError::Internal(e) => e,
+        }
+    }
+}
+
+pub fn init(py: Python, settings: &Settings) -> Result<(), MpError> {
+    let sys = py.import("sys")?;
+    let version: String = sys.get("version")?.extract()?;
+
+    info!("using python: {}", version);
+
+    py.run(PRELUDEPY, None, None)?;
+    // info!("Using prelude: {}", PRELUDEPY);
+    info!("prelude loaded.");
+
+    let main = py.import("__main__")?;
+    main.call1("set_library_paths", (settings.library_paths.clone(),))?;
+    info!("library paths: {:?}", settings.library_paths);
+    Ok(())
+}
+
+/// the files read or imported by the last evaluated script, including
+/// the script itself
+pub fn dependencies(py: Python) -> Result<Vec<PathBuf>, MpError> {
+    let main = py.import("__main__")?;
+    let deps: Vec<String> = main.get("dependencies")?.extract()?;
+    Ok(deps.into_iter().map(PathBuf::from).collect())
+}
+
+/// run the footprint script with the given parameter overrides
+/// and convert all the variants it produces
+pub fn evaluate(
+    py: Python,
+    filename: &str,
+    overrides: &[(String, f64)],
+) -> Result<Evaluation, EvalError> {
+    let dict = PyDict::new(py);
+    for (name, value) in overrides {
+        dict.set_item(name, value)?;
+    }
+    let main = py.import("__main__")?;
+    let res = main.call1("handle_load_python", (filename, dict))?;
+    info!("res: {:?}", res);
+    let res: &PyTuple = res.extract()?;
+    let paraml: &PyList = res.get_item(0).extract()?;
+    let mut params = vec![];
+    for i in 0..paraml.len() {
+        let param: &PyTuple = paraml.get_item(i as isize).extract()?;
+        params.push(Param {
+            name: param.get_item(0).extract()?,
+            value: param.get_item(1).extract()?,
+            min: param.get_item(2).extract()?,
+            max: param.get_item(3).extract()?,
+            integer: param.get_item(4).extract()?,
+        });
+    }
+    let resl: &PyList = res.get_item(1).extract()?;
+    let mut variants = vec![];
+    for i in 0..resl.len() {
+        let variant: &PyTuple = resl.get_item(i as isize).extract()?;
+        let description: String = variant.get_item(0).extract()?;
+        let items: &PyList = variant.get_item(1).extract()?;
+        let mut elements = vec![];
+        // try to convert python provided elements
+        for j in 0..items.len() {
+            let item = items.get_item(j as isize);
+            let gen = item.call_method0("generate")?;
+            //info!("gen: {:?}", gen);
+            let genl: &PyList = gen.extract()?;
+            for k in 0..genl.len() {
+                let item = genl.get_item(k as isize);
+                let x = pyconvert::element(item).map_err(|mut e| {
+                    e.index = j;
+                    EvalError::Convert(e)
+                })?;
+                debug!("x: '{:?}'", x);
+                if let Element::PythonError(e) = x {
+                    return Err(EvalError::Script(e));
+                }
+                elements.push(x);
+            }
+        }
+        variants.push(Variant {
+            params: description,
+            elements,
+        });
+    }
+    Ok(Evaluation { params, variants })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use element::TransformElement;
+    use geometry::Transform;
+    use serde_json::{self, Value};
+    use std::fs;
+    use tempfile::tempdir;
+
+    const SCRIPT: &'static str = r#"
+def footprints():
+    rect = FFab(2.0, 1.0)
+    rect.x = 0.5
+    rect.rot = 10.0
+    chamfered = FFab(2.0, 1.0)
+    chamfered.corner = 0.3
+    smd = Smd(1, (1.0, 0.5), (2.0, 1.0))
+    smd.rot = 45.0
+    pad = Pad(2, 1.2, 0.6, (-2.0, 1.0))
+    name = Name("TRANSFORM")
+    name.y = 3.0
+    plain = [name, Reference(), rect, chamfered, smd, pad,
+             Line((0.0, 0.0), (1.0, 2.0)), Dimension((0.0, 0.0), (3.0, 0.0), 1.0),
+             Desc("transform test"), Tags("a b")]
+    t = Transform.translation(1.0, 2.0).then(Transform.rotation(30.0)).then(Transform.mirroring_x())
+    return [
+        ("plain", plain),
+        ("translate", translate(plain, 1.0, 2.0)),
+        ("rotate", rotate(plain, 30.0)),
+        ("mirror_x", mirror_x(plain)),
+        ("combined", transform(plain, t)),
+    ]
+"#;
+
+    fn assert_close(a: &Value, b: &Value) {
+        match (a, b) {
+            (&Value::Number(ref x), &Value::Number(ref y)) => {
+                let (x, y) = (x.as_f64().unwrap(), y.as_f64().unwrap());
+                assert!((x - y).abs() < 1e-9, "{} != {}", x, y);
+            }
+            (&Value::Array(ref x), &Value::Array(ref y)) => {
+                assert_eq!(x.len(), y.len());
+                for (x, y) in x.iter().zip(y) {
+                    assert_close(x, y);
+                }
+            }
+            (&Value::Object(ref x), &Value::Object(ref y)) => {
+                assert_eq!(x.len(), y.len(), "{:?} != {:?}", x, y);
+                for (k, v) in x {
+                    assert_close(v, &y[k]);
+                }
+            }
+            _ => assert_eq!(a, b),
+        }
+    }
+
+    /// the prelude and TransformElement must transform the same way
+    #[test]
+    fn prelude_transforms_like_rust() {
+        let dir = tempdir().unwrap();
+        let script = dir.path().join("transform.py");
+        fs::write(&script, SCRIPT).unwrap();
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        init(py, &Settings::default()).unwrap();
+        let variants = evaluate(py, script.to_str().unwrap(), &[])
+            .unwrap()
+            .variants;
+        let transforms = [
+            Transform::translate(1.0, 2.0),
+            Transform::rotate(30.0),
+            Transform::mirror_x(),
+            Transform::translate(1.0, 2.0)
+                .then(&Transform::rotate(30.0))
+                .then(&Transform::mirror_x()),
+        ];
+        assert_eq!(variants.len(), transforms.len() + 1);
+        let plain = &variants[0].elements;
+        for (variant, t) in variants[1..].iter().zip(transforms.iter()) {
+            let rust = plain.iter().map(|e| e.transform(t)).collect::<Vec<_>>();
+            assert_close(
+                &serde_json::to_value(&rust).unwrap(),
+                &serde_json::to_value(&variant.elements).unwrap(),
+            );
+        }
+    }
+}
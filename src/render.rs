@@ -0,0 +1,367 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Rendering of the footprint, shared by the view and the image export.
+
+use cairo;
+use cairo_sys;
+use chrono::Local;
+use libc::{c_char, c_double};
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::path::Path;
+
+use element::{Bound, DrawElement};
+use error::MpError;
+use geometry::{Point, Transform};
+use layers::{Layer, LAYER_Z};
+use scheme;
+use DrawState;
+use VERSION;
+
+/// opacity of the front layers when viewing the back
+const FRONT_DIM: f64 = 0.3;
+
+/// space around the footprint in exported images, in mm
+const MARGIN: f64 = 3.0;
+
+/// height of the title block, in pixels for png and points for svg and pdf
+const TITLE_HEIGHT: f64 = 60.0;
+
+/// exported images with a title block are at least this wide
+const TITLE_WIDTH: f64 = 360.0;
+
+/// how to export an image
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    /// pixels per mm for png, points per mm for svg and pdf
+    pub scale: f64,
+    /// add dimensions and a title block with the name and the scale
+    pub title_block: bool,
+}
+
+impl Default for ImageOptions {
+    fn default() -> ImageOptions {
+        ImageOptions {
+            scale: 20.0,
+            title_block: true,
+        }
+    }
+}
+
+pub fn matrix(t: &Transform) -> cairo::Matrix {
+    cairo::Matrix {
+        xx: t.xx,
+        yx: t.yx,
+        xy: t.xy,
+        yy: t.yy,
+        x0: t.x0,
+        y0: t.y0,
+    }
+}
+
+/// transform that fits the bound in a w x h area, centered
+pub fn fit_transform(bound: &Bound, w: f64, h: f64) -> Transform {
+    let dw = (bound.max_x - bound.min_x).max(0.1);
+    let dh = (bound.max_y - bound.min_y).max(0.1);
+    let s = (w / dw).min(h / dh);
+    let cx = (bound.min_x + bound.max_x) / 2.0;
+    let cy = (bound.min_y + bound.max_y) / 2.0;
+    Transform::translate(-cx, -cy)
+        .then(&Transform::scale(s))
+        .then(&Transform::translate(w / 2.0, h / 2.0))
+}
+
+/// mirror around the center of the bound, for the view from the back;
+/// the footprint stays in place
+pub fn back_transform(bound: &Bound) -> Transform {
+    let cx = (bound.min_x + bound.max_x) / 2.0;
+    Transform::translate(-cx, 0.0)
+        .then(&Transform::mirror_x())
+        .then(&Transform::translate(cx, 0.0))
+}
+
+/// part of the w x h area that is visible through t, in mm
+pub fn visible_bound(t: &Transform, w: f64, h: f64) -> Bound {
+    let inv = t.invert();
+    Bound::from_points(&[
+        inv.apply(Point::new(0.0, 0.0)),
+        inv.apply(Point::new(w, 0.0)),
+        inv.apply(Point::new(w, h)),
+        inv.apply(Point::new(0.0, h)),
+    ])
+}
+
+/// draw the background, the grid, the axes and the elements; cr is
+/// already transformed to mm and pixel is the size of a pixel in mm
+pub fn draw_scene(
+    cr: &cairo::Context,
+    draw_state: &DrawState,
+    visible: &Bound,
+    pixel: f64,
+    grid: bool,
+) {
+    scheme::color(Layer::Background).set_source(cr);
+    cr.rectangle(
+        visible.min_x,
+        visible.min_y,
+        visible.max_x - visible.min_x,
+        visible.max_y - visible.min_y,
+    );
+    cr.fill();
+
+    if grid {
+        draw_state.grid.draw(cr, visible, pixel);
+    }
+
+    // draw axes
+    scheme::color(Layer::Axes).set_source(cr);
+    cr.set_line_width(pixel);
+    cr.move_to(visible.min_x, 0.0);
+    cr.line_to(visible.max_x, 0.0);
+    cr.stroke();
+    cr.move_to(0.0, visible.min_y);
+    cr.line_to(0.0, visible.max_y);
+    cr.stroke();
+
+    // draw elements, layer by layer; seen from the back the back layers
+    // are on top
+    let layers: Vec<&(i64, Layer)> = if draw_state.back {
+        LAYER_Z.iter().rev().collect()
+    } else {
+        LAYER_Z.iter().collect()
+    };
+    for (_z, layer) in layers {
+        let layer_view = draw_state.layers.get(layer).cloned().unwrap_or_default();
+        if !layer_view.visible {
+            continue;
+        }
+        let mut opacity = layer_view.opacity;
+        if draw_state.back && layer.is_front() {
+            opacity *= FRONT_DIM;
+        }
+        cr.push_group();
+        for e in &draw_state.elements {
+            e.draw_element(cr, *layer);
+        }
+        cr.pop_group_to_source();
+        cr.paint_with_alpha(opacity);
+    }
+}
+
+fn set_font(cr: &cairo::Context, size: f64) {
+    cr.select_font_face(
+        "Sans",
+        cairo::enums::FontSlant::Normal,
+        cairo::enums::FontWeight::Normal,
+    );
+    cr.set_font_size(size);
+}
+
+/// dimension line from a to b with end ticks, labeled in the middle;
+/// a and b are in device coordinates, the ticks are on the side of n
+fn draw_dimension(cr: &cairo::Context, a: Point, b: Point, n: Point, label: &str) {
+    cr.move_to(a.x, a.y);
+    cr.line_to(b.x, b.y);
+    for p in &[a, b] {
+        cr.move_to(p.x - n.x * 5.0, p.y - n.y * 5.0);
+        cr.line_to(p.x + n.x * 5.0, p.y + n.y * 5.0);
+    }
+    cr.stroke();
+    set_font(cr, 11.0);
+    let ext = cr.text_extents(label);
+    let mx = (a.x + b.x) / 2.0;
+    let my = (a.y + b.y) / 2.0;
+    if n.x == 0.0 {
+        // horizontal, label below the line
+        cr.move_to(mx - ext.width / 2.0 - ext.x_bearing, my + ext.height + 6.0);
+    } else {
+        // vertical, label right of the line
+        cr.move_to(mx + 6.0, my + ext.height / 2.0);
+    }
+    cr.show_text(label);
+}
+
+/// dimensions of the footprint and the title block, in device coordinates
+fn draw_title_block(
+    cr: &cairo::Context,
+    draw_state: &DrawState,
+    t: &Transform,
+    options: &ImageOptions,
+    w: f64,
+    scene_h: f64,
+) {
+    let b = &draw_state.bound;
+    let offset = MARGIN * options.scale / 2.0;
+    let corners = [
+        t.apply(Point::new(b.min_x, b.max_y)),
+        t.apply(Point::new(b.max_x, b.max_y)),
+        t.apply(Point::new(b.max_x, b.min_y)),
+    ];
+    // the back view is mirrored, so sort on device x
+    let left = corners[0].x.min(corners[1].x);
+    let right = corners[0].x.max(corners[1].x);
+    let bottom = corners[1].y;
+    let top = corners[2].y;
+    scheme::color(Layer::Grid).set_source(cr);
+    cr.set_line_width(1.0);
+    draw_dimension(
+        cr,
+        Point::new(left, bottom + offset),
+        Point::new(right, bottom + offset),
+        Point::new(0.0, 1.0),
+        &format!("{:.2} mm", b.max_x - b.min_x),
+    );
+    draw_dimension(
+        cr,
+        Point::new(right + offset, top),
+        Point::new(right + offset, bottom),
+        Point::new(1.0, 0.0),
+        &format!("{:.2} mm", b.max_y - b.min_y),
+    );
+
+    // title block
+    cr.rectangle(0.5, scene_h + 0.5, w - 1.0, TITLE_HEIGHT - 1.0);
+    cr.move_to(0.5, scene_h + TITLE_HEIGHT / 2.0);
+    cr.line_to(w - 0.5, scene_h + TITLE_HEIGHT / 2.0);
+    cr.stroke();
+    scheme::color(Layer::FSilkS).set_source(cr);
+    set_font(cr, 14.0);
+    cr.move_to(8.0, scene_h + TITLE_HEIGHT / 2.0 - 9.0);
+    let side = if draw_state.back { " (back)" } else { "" };
+    cr.show_text(&format!("{}{}", draw_state.name(), side));
+    set_font(cr, 10.0);
+    cr.move_to(8.0, scene_h + TITLE_HEIGHT - 11.0);
+    cr.show_text(&format!(
+        "{:.2} x {:.2} mm   scale {} / mm   madparts {}   {}",
+        b.max_x - b.min_x,
+        b.max_y - b.min_y,
+        options.scale,
+        VERSION,
+        Local::now().format("%Y-%m-%d")
+    ));
+}
+
+/// draw the footprint on a w x h area with the title block below it
+fn draw_image(
+    cr: &cairo::Context,
+    draw_state: &DrawState,
+    options: &ImageOptions,
+    w: f64,
+    scene_h: f64,
+    h: f64,
+) {
+    scheme::color(Layer::Background).set_source(cr);
+    cr.rectangle(0.0, 0.0, w, h);
+    cr.fill();
+
+    let b = &draw_state.bound;
+    let mut t = Transform::translate(-(b.min_x + b.max_x) / 2.0, -(b.min_y + b.max_y) / 2.0)
+        .then(&Transform::scale(options.scale))
+        .then(&Transform::translate(w / 2.0, scene_h / 2.0));
+    if draw_state.back {
+        t = back_transform(b).then(&t);
+    }
+    cr.save();
+    cr.transform(matrix(&t));
+    draw_scene(
+        cr,
+        draw_state,
+        &visible_bound(&t, w, scene_h),
+        1.0 / options.scale,
+        false,
+    );
+    cr.restore();
+
+    if options.title_block {
+        draw_title_block(cr, draw_state, &t, options, w, scene_h);
+    }
+}
+
+extern "C" {
+    // cairo-rs 0.4 wraps the pdf surface, but not the svg one
+    fn cairo_svg_surface_create(
+        filename: *const c_char,
+        width_in_points: c_double,
+        height_in_points: c_double,
+    ) -> *mut cairo_sys::cairo_surface_t;
+}
+
+fn svg_surface(path: &Path, w: f64, h: f64) -> Result<cairo::Surface, MpError> {
+    let filename = CString::new(path.to_string_lossy().into_owned())
+        .map_err(|e| MpError::Save(format!("{:?}", e)))?;
+    let surface = unsafe { cairo_svg_surface_create(filename.as_ptr(), w, h) };
+    Ok(unsafe { cairo::Surface::from_raw_full(surface) })
+}
+
+/// draw one page on an svg or pdf surface and write the file
+fn write_vector_image(
+    surface: &cairo::Surface,
+    path: &Path,
+    draw_state: &DrawState,
+    options: &ImageOptions,
+    w: f64,
+    scene_h: f64,
+    h: f64,
+) -> Result<(), MpError> {
+    {
+        let cr = cairo::Context::new(surface);
+        draw_image(&cr, draw_state, options, w, scene_h, h);
+        cr.show_page();
+    }
+    // the file is only complete once the surface is finished
+    unsafe { cairo_sys::cairo_surface_finish(surface.to_raw_none()) };
+    match surface.status() {
+        cairo::Status::Success => Ok(()),
+        status => Err(MpError::Save(format!("{}: {:?}", path.display(), status))),
+    }
+}
+
+/// export the footprint as png, svg or pdf, depending on the extension
+pub fn export_image(
+    draw_state: &DrawState,
+    path: &Path,
+    options: &ImageOptions,
+) -> Result<(), MpError> {
+    let b = &draw_state.bound;
+    let mut w = (b.max_x - b.min_x + 2.0 * MARGIN) * options.scale;
+    let scene_h = (b.max_y - b.min_y + 2.0 * MARGIN) * options.scale;
+    let mut h = scene_h;
+    if options.title_block {
+        w = w.max(TITLE_WIDTH);
+        h += TITLE_HEIGHT;
+    }
+    let extension = path.extension()
+        .and_then(OsStr::to_str)
+        .unwrap_or("")
+        .to_lowercase();
+    match extension.as_str() {
+        "png" => {
+            let surface =
+                cairo::ImageSurface::create(cairo::enums::Format::ARgb32, w as i32, h as i32)
+                    .map_err(|e| MpError::Save(format!("{:?}", e)))?;
+            draw_image(&cairo::Context::new(&surface), draw_state, options, w, scene_h, h);
+            let mut f = fs::File::create(path)?;
+            surface
+                .write_to_png(&mut f)
+                .map_err(|e| MpError::Save(format!("{:?}", e)))?;
+        }
+        "svg" => {
+            let surface = svg_surface(path, w, h)?;
+            write_vector_image(&surface, path, draw_state, options, w, scene_h, h)?;
+        }
+        "pdf" => {
+            // PDFSurface::create panics when cairo can't open the file
+            fs::File::create(path)?;
+            let surface = cairo::PDFSurface::create(path, w, h);
+            write_vector_image(&surface, path, draw_state, options, w, scene_h, h)?;
+        }
+        _ => {
+            return Err(MpError::Save(format!(
+                "{}: unknown image format, use png, svg or pdf",
+                path.display()
+            )))
+        }
+    }
+    info!("exported {}", path.display());
+    Ok(())
+}
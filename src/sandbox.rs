@@ -0,0 +1,382 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Restricted evaluation of untrusted footprint scripts.
+//
+// The worker process enters the sandbox before it starts python: it moves
+// to new user, mount and network namespaces so it has no network and sees
+// the filesystem read-only, except for the directory of the script, and it
+// gets rlimits on cpu time, memory and file size.
+//
+// The read-only mounts are locked by moving to a nested user namespace,
+// then all capabilities are dropped and a seccomp filter refuses the
+// system calls that change mounts or namespaces, so the script can't undo
+// any of it.
+//
+// The namespaces and the seccomp filter are only there on linux, on
+// x86_64 and aarch64; elsewhere entering the sandbox fails.
+
+use std::path::Path;
+
+use error::MpError;
+
+/// default cpu time limit in seconds
+const CPU_TIME: u64 = 10;
+/// default memory limit in MB
+const MEMORY: u64 = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Limits {
+    /// cpu time in seconds
+    pub cpu_time: u64,
+    /// address space in MB
+    pub memory: u64,
+}
+
+impl Limits {
+    pub fn new(cpu_time: Option<u64>, memory: Option<u64>) -> Limits {
+        Limits {
+            cpu_time: cpu_time.unwrap_or(CPU_TIME),
+            memory: memory.unwrap_or(MEMORY),
+        }
+    }
+}
+
+/// enter the sandbox; called by the worker before it starts python
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn enter(script_dir: &Path, limits: &Limits) -> Result<(), MpError> {
+    linux::enter(script_dir, limits)
+        .map_err(|e| MpError::Other(format!("Failed to set up sandbox: {}", e)))?;
+    info!("sandbox entered for {}", script_dir.display());
+    Ok(())
+}
+
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+pub fn enter(_script_dir: &Path, _limits: &Limits) -> Result<(), MpError> {
+    Err(MpError::Other("sandbox unsupported on this platform".into()))
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod linux {
+    use std::ffi::CString;
+    use std::fs;
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::str;
+
+    use libc;
+
+    use super::Limits;
+
+    /// maximum size of a file the script can write in bytes
+    const FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+    /// pseudo filesystems that can stay as they are, they are often
+    /// locked in a user namespace
+    const PSEUDO_FS: &'static [&'static str] = &[
+        "autofs",
+        "binfmt_misc",
+        "bpf",
+        "cgroup",
+        "cgroup2",
+        "configfs",
+        "debugfs",
+        "devpts",
+        "fusectl",
+        "hugetlbfs",
+        "mqueue",
+        "proc",
+        "pstore",
+        "securityfs",
+        "sysfs",
+        "tracefs",
+    ];
+
+    /// system calls the sandboxed script gets EPERM for
+    const DENIED_SYSCALLS: &'static [libc::c_long] = &[
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        // the new mount api, these numbers are the same on every architecture
+        428, // open_tree
+        429, // move_mount
+        430, // fsopen
+        431, // fsconfig
+        432, // fsmount
+        433, // fspick
+        442, // mount_setattr
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// x32 system calls on x86_64 have this bit set
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    // see linux/filter.h and linux/seccomp.h
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JEQ_K: u16 = 0x15;
+    const BPF_JGE_K: u16 = 0x35;
+    const BPF_RET_K: u16 = 0x06;
+    const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+    /// offsets in struct seccomp_data
+    const SECCOMP_DATA_NR: u32 = 0;
+    const SECCOMP_DATA_ARCH: u32 = 4;
+
+    #[repr(C)]
+    struct SockFilter {
+        code: u16,
+        jt: u8,
+        jf: u8,
+        k: u32,
+    }
+
+    #[repr(C)]
+    struct SockFprog {
+        len: libc::c_ushort,
+        filter: *const SockFilter,
+    }
+
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    fn check(r: libc::c_int) -> io::Result<()> {
+        if r == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn set_limit(resource: libc::c_int, soft: u64, hard: u64) -> io::Result<()> {
+        let rlim = libc::rlimit {
+            rlim_cur: soft as libc::rlim_t,
+            rlim_max: hard as libc::rlim_t,
+        };
+        check(unsafe { libc::setrlimit(resource, &rlim) })
+    }
+
+    fn c_path(path: &str) -> io::Result<CString> {
+        Ok(CString::new(path)?)
+    }
+
+    /// mount points in /proc/self/mountinfo escape spaces and such as \ooo
+    fn unescape(s: &str) -> String {
+        let b = s.as_bytes();
+        let mut out = vec![];
+        let mut i = 0;
+        while i < b.len() {
+            if b[i] == b'\\' && i + 3 < b.len() {
+                let octal = str::from_utf8(&b[i + 1..i + 4]).ok();
+                if let Some(c) = octal.and_then(|o| u8::from_str_radix(o, 8).ok()) {
+                    out.push(c);
+                    i += 4;
+                    continue;
+                }
+            }
+            out.push(b[i]);
+            i += 1;
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    fn remount_readonly(mount_point: &str) -> io::Result<()> {
+        let target = c_path(mount_point)?;
+        // a bind remount in a user namespace has to keep the existing
+        // nosuid, nodev and noexec flags
+        let mut st: libc::statvfs = unsafe { mem::zeroed() };
+        check(unsafe { libc::statvfs(target.as_ptr(), &mut st) })?;
+        let mut flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY;
+        for &(st_flag, ms_flag) in &[
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ] {
+            if st.f_flag & st_flag != 0 {
+                flags |= ms_flag;
+            }
+        }
+        check(unsafe {
+            libc::mount(
+                ::std::ptr::null(),
+                target.as_ptr(),
+                ::std::ptr::null(),
+                flags,
+                ::std::ptr::null(),
+            )
+        })
+    }
+
+    /// move to a new user namespace, keeping our own user and group
+    fn new_user_namespace(flags: libc::c_int) -> io::Result<()> {
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+        check(unsafe { libc::unshare(libc::CLONE_NEWUSER | flags) })?;
+        fs::write("/proc/self/setgroups", "deny")?;
+        fs::write("/proc/self/uid_map", format!("{} {} 1", uid, uid))?;
+        fs::write("/proc/self/gid_map", format!("{} {} 1", gid, gid))
+    }
+
+    /// drop all capabilities, including those in the bounding set
+    fn drop_capabilities() -> io::Result<()> {
+        for cap in 0..64 {
+            let r = unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap, 0, 0, 0) };
+            // EINVAL past the last capability the kernel knows
+            if r == -1 && io::Error::last_os_error().raw_os_error() != Some(libc::EINVAL) {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let header = CapHeader {
+            version: LINUX_CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let data = [CapData::default(), CapData::default()];
+        check(unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) } as libc::c_int)
+    }
+
+    /// refuse the system calls in DENIED_SYSCALLS
+    fn install_seccomp_filter() -> io::Result<()> {
+        let stmt = |code, k| SockFilter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let n = DENIED_SYSCALLS.len() as u8;
+        let mut filter = vec![
+            stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+            SockFilter {
+                code: BPF_JEQ_K,
+                jt: 1,
+                jf: 0,
+                k: AUDIT_ARCH,
+            },
+            stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+            SockFilter {
+                code: BPF_JGE_K,
+                jt: n + 1,
+                jf: 0,
+                k: X32_SYSCALL_BIT,
+            },
+        ];
+        for (i, &nr) in DENIED_SYSCALLS.iter().enumerate() {
+            // jump over the remaining checks and the allow to the deny
+            filter.push(SockFilter {
+                code: BPF_JEQ_K,
+                jt: n - i as u8,
+                jf: 0,
+                k: nr as u32,
+            });
+        }
+        filter.push(stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+        filter.push(stmt(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        let prog = SockFprog {
+            len: filter.len() as libc::c_ushort,
+            filter: filter.as_ptr(),
+        };
+        check(unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const SockFprog,
+                0,
+                0,
+            )
+        })
+    }
+
+    fn isolate(script_dir: &Path) -> io::Result<()> {
+        new_user_namespace(libc::CLONE_NEWNS | libc::CLONE_NEWNET)?;
+
+        // don't let any of the mount changes below leak out
+        let root = c_path("/")?;
+        check(unsafe {
+            libc::mount(
+                ::std::ptr::null(),
+                root.as_ptr(),
+                ::std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                ::std::ptr::null(),
+            )
+        })?;
+
+        // make the script directory a mount of its own, so it can stay writable
+        let dir_str = script_dir.to_string_lossy().into_owned();
+        let dir = CString::new(script_dir.as_os_str().as_bytes())?;
+        check(unsafe {
+            libc::mount(
+                dir.as_ptr(),
+                dir.as_ptr(),
+                ::std::ptr::null(),
+                libc::MS_BIND | libc::MS_REC,
+                ::std::ptr::null(),
+            )
+        })?;
+
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+        for line in mountinfo.lines() {
+            // see proc(5): field 5 is the mount point, the filesystem type
+            // follows the " - " separator
+            let mount_point = match line.split(' ').nth(4) {
+                Some(m) => unescape(m),
+                None => continue,
+            };
+            if mount_point == dir_str || mount_point.starts_with(&format!("{}/", dir_str)) {
+                continue;
+            }
+            let fs_type = line.split(" - ").nth(1).and_then(|r| r.split(' ').next());
+            // proc stays writable for the uid and gid maps of the nested user
+            // namespace below; without capabilities that is all it is good for
+            if fs_type == Some("proc") {
+                continue;
+            }
+            if let Err(e) = remount_readonly(&mount_point) {
+                match fs_type {
+                    Some(t) if PSEUDO_FS.contains(&t) => (),
+                    _ => return Err(e),
+                }
+            }
+        }
+
+        // mounts copied into a mount namespace of a less privileged user
+        // namespace are locked: they can't be remounted writable or unmounted
+        new_user_namespace(libc::CLONE_NEWNS)?;
+        drop_capabilities()?;
+        check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+        install_seccomp_filter()
+    }
+
+    pub fn enter(script_dir: &Path, limits: &Limits) -> io::Result<()> {
+        set_limit(libc::RLIMIT_CPU, limits.cpu_time, limits.cpu_time + 1)?;
+        let memory = limits.memory * 1024 * 1024;
+        set_limit(libc::RLIMIT_AS, memory, memory)?;
+        set_limit(libc::RLIMIT_FSIZE, FILE_SIZE, FILE_SIZE)?;
+        set_limit(libc::RLIMIT_CORE, 0, 0)?;
+        isolate(script_dir)
+    }
+}
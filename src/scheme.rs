@@ -0,0 +1,141 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Color schemes for the view. The built-in schemes are in schemes/,
+// users can add their own json files to ~/.config/madparts/schemes.
+
+use serde_json;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use error::MpError;
+use layers::{Color, Layer, LAYER_Z};
+
+const BUILTIN: &'static [&'static str] = &[
+    include_str!("../schemes/default.json"),
+    include_str!("../schemes/kicad.json"),
+    include_str!("../schemes/classic.json"),
+];
+
+/// color of a layer no scheme has a color for
+const UNKNOWN: (f64, f64, f64, f64) = (1.0, 0.0, 1.0, 0.7);
+
+/// a color scheme as stored in a file
+#[derive(Deserialize)]
+struct SchemeFile {
+    name: String,
+    /// red, green, blue and alpha by layer name
+    colors: HashMap<String, (f64, f64, f64, f64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColorScheme {
+    pub name: String,
+    colors: HashMap<Layer, Color>,
+}
+
+impl ColorScheme {
+    fn from_json(data: &str) -> Result<ColorScheme, MpError> {
+        let file: SchemeFile = serde_json::from_str(data)?;
+        let mut colors = HashMap::new();
+        for (name, color) in file.colors {
+            match LAYER_Z.iter().find(|(_, l)| l.to_string() == name) {
+                Some(&(_, layer)) => {
+                    colors.insert(layer, Color::from(color));
+                }
+                None => warn!("Unknown layer {} in color scheme {}", name, file.name),
+            }
+        }
+        Ok(ColorScheme {
+            name: file.name,
+            colors,
+        })
+    }
+}
+
+lazy_static! {
+    static ref DEFAULT: ColorScheme = ColorScheme::from_json(BUILTIN[0]).unwrap();
+    static ref CURRENT: RwLock<ColorScheme> = RwLock::new(DEFAULT.clone());
+}
+
+/// color of a layer in the current scheme; a scheme that leaves out a
+/// layer gets the color of the default scheme
+pub fn color(layer: Layer) -> Color {
+    let current = CURRENT.read().unwrap();
+    current
+        .colors
+        .get(&layer)
+        .or_else(|| DEFAULT.colors.get(&layer))
+        .cloned()
+        .unwrap_or_else(|| Color::from(UNKNOWN))
+}
+
+pub fn current_name() -> String {
+    CURRENT.read().unwrap().name.clone()
+}
+
+pub fn set_scheme(scheme: &ColorScheme) {
+    info!("Using color scheme {}", scheme.name);
+    *CURRENT.write().unwrap() = scheme.clone();
+}
+
+/// use the scheme with the given name, if there is one
+pub fn select(schemes: &[ColorScheme], name: Option<&String>) {
+    if let Some(name) = name {
+        match schemes.iter().find(|s| s.name == *name) {
+            Some(s) => set_scheme(s),
+            None => warn!("Unknown color scheme {}", name),
+        }
+    }
+}
+
+/// the directory with the schemes of the user, None without a home
+/// directory
+fn scheme_dir() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("madparts").join("schemes"))
+}
+
+/// the built-in schemes and the schemes of the user; a user scheme with
+/// the name of a built-in one replaces it
+pub fn load_schemes() -> Vec<ColorScheme> {
+    let mut schemes = BUILTIN
+        .iter()
+        .map(|data| ColorScheme::from_json(data).unwrap())
+        .collect::<Vec<ColorScheme>>();
+    let dir = match scheme_dir() {
+        Some(dir) => dir,
+        None => return schemes,
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            info!("No color schemes in {}: {:?}", dir.display(), e);
+            return schemes;
+        }
+    };
+    let mut paths = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|x| x == "json").unwrap_or(false))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    for path in paths {
+        let scheme = fs::read_to_string(&path)
+            .map_err(MpError::from)
+            .and_then(|data| ColorScheme::from_json(&data));
+        match scheme {
+            Ok(scheme) => {
+                info!("Loaded color scheme {} from {}", scheme.name, path.display());
+                schemes.retain(|s| s.name != scheme.name);
+                schemes.push(scheme);
+            }
+            Err(e) => warn!("Error loading color scheme {}: {:?}", path.display(), e),
+        }
+    }
+    schemes
+}
@@ -1,24 +1,148 @@
 // (c) 2018 Joost Yervante Damad <joost@damad.be>
 
 use serde_json;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::path::PathBuf;
+
+use drc::FabProfile;
+use grid::Grid;
+use layers::{Layer, LayerView};
 
 #[derive(Debug, Default, Deserialize)]
 pub struct Settings {
+    /// checkout of the KiCad library utils, for check_kicad_mod.py
     pub klc_dir: Option<String>,
+    /// python interpreter to run the KLC script with (default: python3)
+    pub klc_python: Option<String>,
+    /// KLC script, relative to klc_dir (default: check_kicad_mod.py)
+    pub klc_script: Option<String>,
+    /// extra arguments for the KLC script
+    #[serde(default)]
+    pub klc_args: Vec<String>,
+    /// extra directories footprint scripts can import modules from
+    #[serde(default)]
+    pub library_paths: Vec<String>,
+    /// seconds after which a footprint script evaluation is stopped
+    pub eval_timeout: Option<f64>,
+    /// evaluate scripts in a sandbox, see sandbox.rs
+    #[serde(default)]
+    pub sandbox: bool,
+    /// cpu time limit in seconds for sandboxed scripts
+    pub sandbox_cpu_time: Option<u64>,
+    /// memory limit in MB for sandboxed scripts
+    pub sandbox_memory: Option<u64>,
+    /// fab profiles for DRC, by name
+    #[serde(default)]
+    pub fab_profiles: HashMap<String, FabProfile>,
+    /// name of the fab profile to use
+    pub fab_profile: Option<String>,
+    /// visibility and opacity of the layers in the view, by layer name
+    #[serde(default)]
+    pub layers: HashMap<String, LayerView>,
+    /// name of the color scheme of the view, see scheme.rs
+    pub color_scheme: Option<String>,
+    /// grid in the view, see grid.rs
+    #[serde(default)]
+    pub grid: Grid,
+}
+
+impl Settings {
+    /// the selected fab profile, or the built-in default
+    pub fn fab_profile(&self) -> FabProfile {
+        match self.fab_profile {
+            Some(ref name) => match self.fab_profiles.get(name) {
+                Some(profile) => profile.clone(),
+                None => {
+                    warn!("Unknown fab profile {}, using defaults", name);
+                    FabProfile::default()
+                }
+            },
+            None => FabProfile::default(),
+        }
+    }
+
+    /// the layer views that differ from the default
+    pub fn layer_views(&self) -> HashMap<Layer, LayerView> {
+        let mut views = HashMap::new();
+        for (name, view) in &self.layers {
+            match name.parse::<Layer>() {
+                Ok(layer) => {
+                    views.insert(layer, *view);
+                }
+                Err(()) => warn!("Unknown layer {} in settings", name),
+            }
+        }
+        views
+    }
+
+    pub fn set_layer_views(&mut self, views: &HashMap<Layer, LayerView>) {
+        self.layers = views
+            .iter()
+            .map(|(layer, view)| (layer.to_string(), *view))
+            .collect();
+    }
+}
+
+/// ~/.madparts.conf, None if there is no home directory
+fn settings_filename() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".madparts.conf"))
 }
 
 pub fn load_settings() -> Settings {
-    let filename = format!("{}/.madparts.conf", env!("HOME"));
+    let filename = match settings_filename() {
+        Some(filename) => filename,
+        None => {
+            info!("HOME is not set, using default settings");
+            return Settings::default();
+        }
+    };
     match fs::read_to_string(&filename) {
         Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
-            warn!("Error json decoding file {}: {}", filename, e);
+            warn!("Error json decoding file {}: {}", filename.display(), e);
             Settings::default()
         }),
         Err(e) => {
-            info!("Error reading file {}: {:?}", filename, e);
+            info!("Error reading file {}: {:?}", filename.display(), e);
             info!("Using default settings");
             Settings::default()
         }
     }
 }
+
+/// store the settings changed from the GUI; the rest of the file is
+/// left as the user wrote it
+pub fn save_gui_settings(settings: &Settings) {
+    let filename = match settings_filename() {
+        Some(filename) => filename,
+        None => {
+            warn!("Not saving settings, HOME is not set");
+            return;
+        }
+    };
+    let mut json = match fs::read_to_string(&filename) {
+        Ok(data) => match serde_json::from_str::<serde_json::Value>(&data) {
+            Ok(ref v) if v.is_object() => v.clone(),
+            _ => {
+                // don't overwrite a file the user still has to fix
+                warn!("Not saving settings, {} is not a json object", filename.display());
+                return;
+            }
+        },
+        Err(_) => serde_json::Value::Object(serde_json::Map::new()),
+    };
+    let res = serde_json::to_value(&settings.layers)
+        .and_then(|layers| {
+            json["layers"] = layers;
+            if let Some(ref name) = settings.color_scheme {
+                json["color_scheme"] = serde_json::Value::String(name.clone());
+            }
+            serde_json::to_string_pretty(&json)
+        })
+        .map_err(|e| e.to_string())
+        .and_then(|data| fs::write(&filename, data).map_err(|e| e.to_string()));
+    if let Err(e) = res {
+        warn!("Error writing file {}: {}", filename.display(), e);
+    }
+}
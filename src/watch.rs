@@ -0,0 +1,104 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+use error::MpError;
+
+/// watches a set of files, possibly spread over several directories
+///
+/// The directories are watched instead of the files themselves, as
+/// editors often replace a file instead of writing to it.
+pub struct Watcher {
+    ino: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    files: HashSet<PathBuf>,
+}
+
+fn canonical(path: PathBuf) -> PathBuf {
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+impl Watcher {
+    pub fn new() -> Result<Watcher, MpError> {
+        let ino = match Inotify::init() {
+            Ok(ino) => ino,
+            Err(err) => {
+                error!("Failed to initialize INotify");
+                return Err(err.into());
+            }
+        };
+        Ok(Watcher {
+            ino,
+            dirs: HashMap::new(),
+            files: HashSet::new(),
+        })
+    }
+
+    /// watch exactly these files from now on
+    pub fn set_files(&mut self, files: Vec<PathBuf>) {
+        let files: HashSet<PathBuf> = files.into_iter().map(canonical).collect();
+        if files == self.files {
+            return;
+        }
+        let dirs: HashSet<PathBuf> = files
+            .iter()
+            .filter_map(|f| f.parent().map(|d| d.into()))
+            .collect();
+        // drop directories that are no longer needed
+        let old: Vec<WatchDescriptor> = self.dirs
+            .iter()
+            .filter(|(_, d)| !dirs.contains(*d))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in old {
+            if let Some(dir) = self.dirs.remove(&wd) {
+                debug!("unwatching {}", dir.display());
+            }
+            let _ = self.ino.rm_watch(wd);
+        }
+        for dir in dirs {
+            if self.dirs.values().any(|d| *d == dir) {
+                continue;
+            }
+            // close_write,moved_to,create indicate the file was possibly messed with
+            match self.ino.add_watch(
+                &dir,
+                WatchMask::CREATE | WatchMask::MOVED_TO | WatchMask::CLOSE_WRITE,
+            ) {
+                Ok(wd) => {
+                    debug!("watching {}", dir.display());
+                    self.dirs.insert(wd, dir);
+                }
+                Err(e) => warn!("Failed to watch {}: {:?}", dir.display(), e),
+            }
+        }
+        self.files = files;
+    }
+
+    /// true if any of the watched files was modified since the last poll
+    pub fn poll(&mut self) -> bool {
+        let mut buffer = [0; 1024];
+        let mut modified = false;
+        let events = match self.ino.read_events(&mut buffer) {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Failed to read file events: {}", e);
+                return false;
+            }
+        };
+        for event in events {
+            if let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) {
+                let path = dir.join(name);
+                if self.files.contains(&path) {
+                    debug!("modified: {}", path.display());
+                    modified = true;
+                }
+            }
+        }
+        modified
+    }
+}
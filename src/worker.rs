@@ -0,0 +1,291 @@
+// (c) 2018 Joost Yervante Damad <joost@damad.be>
+
+// Footprint scripts are evaluated in a separate madparts process, so that
+// a runaway script can be stopped without taking the GUI down with it.
+// The worker gets its Input on stdin and writes its Output as json on
+// stdout; that is all that comes back from a possibly sandboxed script.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc;
+use pyo3::Python;
+use serde_json;
+
+use element::PythonError;
+use error::MpError;
+use python::{self, EvalError, Evaluation};
+use sandbox::{self, Limits};
+use settings::{self, Settings};
+
+/// default evaluation timeout in seconds
+const TIMEOUT: f64 = 10.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Input {
+    overrides: Vec<(String, f64)>,
+    /// run the script in a sandbox with these limits
+    sandbox: Option<Limits>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    pub evaluation: Result<Evaluation, EvalError>,
+    /// files the script depends on, empty if unknown
+    pub dependencies: Vec<PathBuf>,
+}
+
+pub struct Reply {
+    /// id of the job this is a reply to
+    pub id: usize,
+    pub output: Output,
+}
+
+/// the worker process of a job, shared with the thread supervising it;
+/// None before it is started and after it is done
+type Worker = Arc<Mutex<Option<Child>>>;
+
+/// a running evaluation; dropping it stops the worker process
+pub struct Job {
+    cancel: Arc<AtomicBool>,
+    worker: Worker,
+}
+
+impl Job {
+    /// stop the evaluation and wait for the worker process to exit; no
+    /// reply will be sent
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        stop(&self.worker);
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// start evaluating a script in a worker process; the result is sent
+/// to tx when it is done
+pub fn spawn(
+    id: usize,
+    filename: &str,
+    overrides: Vec<(String, f64)>,
+    settings: &Settings,
+    tx: Sender<Reply>,
+) -> Job {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let cancel_thread = cancel.clone();
+    let worker: Worker = Arc::new(Mutex::new(None));
+    let worker_thread = worker.clone();
+    let filename = filename.to_string();
+    let input = make_input(overrides, settings);
+    let timeout = settings.eval_timeout.unwrap_or(TIMEOUT);
+    thread::spawn(move || {
+        let result = supervise(&filename, &input, timeout, &cancel_thread, &worker_thread);
+        let output = match result {
+            Ok(Some(output)) => output,
+            Ok(None) => return,
+            Err(e) => Output {
+                evaluation: Err(EvalError::Internal(e)),
+                dependencies: vec![],
+            },
+        };
+        let _ = tx.send(Reply { id, output });
+    });
+    Job { cancel, worker }
+}
+
+fn make_input(overrides: Vec<(String, f64)>, settings: &Settings) -> Input {
+    let sandbox = if settings.sandbox {
+        Some(Limits::new(
+            settings.sandbox_cpu_time,
+            settings.sandbox_memory,
+        ))
+    } else {
+        None
+    };
+    Input { overrides, sandbox }
+}
+
+/// evaluate a script in a worker process and wait for the result
+pub fn evaluate(
+    filename: &str,
+    overrides: Vec<(String, f64)>,
+    settings: &Settings,
+) -> Result<Output, MpError> {
+    let input = make_input(overrides, settings);
+    let timeout = settings.eval_timeout.unwrap_or(TIMEOUT);
+    let worker = Mutex::new(None);
+    let output = supervise(filename, &input, timeout, &AtomicBool::new(false), &worker)?;
+    Ok(output.unwrap())
+}
+
+/// start the worker process and send it the input
+fn start_worker(filename: &str, input: &Input) -> Result<Child, MpError> {
+    let mut child = Command::new(env::current_exe()?)
+        .arg("worker")
+        .arg(filename)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let sent = match child.stdin.take() {
+        Some(mut stdin) => serde_json::to_string(input)
+            .map_err(MpError::from)
+            .and_then(|data| Ok(stdin.write_all(data.as_bytes())?)),
+        None => Ok(()),
+    };
+    if let Err(e) = sent {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
+    Ok(child)
+}
+
+/// stop the worker process, if it is still running
+fn stop(worker: &Mutex<Option<Child>>) {
+    if let Some(mut child) = worker.lock().unwrap().take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// run the worker process until it is done, times out or is cancelled
+fn supervise(
+    filename: &str,
+    input: &Input,
+    timeout: f64,
+    cancel: &AtomicBool,
+    worker: &Mutex<Option<Child>>,
+) -> Result<Option<Output>, MpError> {
+    let mut stdout = {
+        // start under the lock, so a cancel either comes before and the
+        // worker isn't started, or after and it sees the worker
+        let mut worker = worker.lock().unwrap();
+        if cancel.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let mut child = start_worker(filename, input)?;
+        let stdout = child.stdout.take().unwrap();
+        *worker = Some(child);
+        stdout
+    };
+    // read stdout in a separate thread, to avoid the worker blocking on
+    // a full pipe for large footprints
+    let reader = thread::spawn(move || {
+        let mut data = String::new();
+        let _ = stdout.read_to_string(&mut data);
+        data
+    });
+    let start = Instant::now();
+    let timeout_duration = Duration::from_millis((timeout * 1000.0) as u64);
+    loop {
+        let exited = {
+            let mut worker = worker.lock().unwrap();
+            let status = match worker.as_mut() {
+                // the job was cancelled, which stopped the worker
+                None => return Ok(None),
+                Some(child) => child.try_wait()?,
+            };
+            if status.is_some() {
+                worker.take();
+            }
+            status
+        };
+        if let Some(status) = exited {
+            let data = reader.join().unwrap_or_default();
+            if !status.success() {
+                let limits = if input.sandbox.is_some() {
+                    " (sandbox limits exceeded?)"
+                } else {
+                    ""
+                };
+                return Err(MpError::Other(format!(
+                    "evaluation worker failed: {}{}",
+                    status, limits
+                )));
+            }
+            return Ok(Some(serde_json::from_str(&data)?));
+        }
+        if cancel.load(Ordering::SeqCst) {
+            debug!("cancelling evaluation of {}", filename);
+            stop(worker);
+            return Ok(None);
+        }
+        if start.elapsed() > timeout_duration {
+            warn!("evaluation of {} timed out", filename);
+            stop(worker);
+            let message = format!(
+                "Evaluation of {} was stopped after {} seconds.\nDoes it contain an endless loop?\n",
+                filename, timeout
+            );
+            return Ok(Some(Output {
+                evaluation: Err(EvalError::Script(PythonError::new(message))),
+                dependencies: vec![],
+            }));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// keep stdout for the Output and send everything else written to fd 1,
+/// by the script, a C extension or a subprocess, to stderr
+fn reserve_stdout() -> io::Result<File> {
+    let fd = unsafe { libc::fcntl(1, libc::F_DUPFD_CLOEXEC, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let reply = unsafe { File::from_raw_fd(fd) };
+    if unsafe { libc::dup2(2, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(reply)
+}
+
+/// entry point of the worker process
+pub fn run(filename: &str) -> Result<(), MpError> {
+    let mut reply = reserve_stdout()?;
+    let settings = settings::load_settings();
+    let mut data = String::new();
+    io::stdin().read_to_string(&mut data)?;
+    let input: Input = serde_json::from_str(&data)?;
+
+    if let Some(ref limits) = input.sandbox {
+        let path = Path::new(filename).canonicalize()?;
+        if let Err(e) = sandbox::enter(path.parent().unwrap(), limits) {
+            // refuse to run the script outside of the sandbox
+            let output = Output {
+                evaluation: Err(e.into()),
+                dependencies: vec![],
+            };
+            serde_json::to_writer(&mut reply, &output)?;
+            return Ok(());
+        }
+    }
+
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+
+    let evaluation = match python::init(py, &settings) {
+        Ok(()) => python::evaluate(py, filename, &input.overrides),
+        Err(e) => Err(e.into()),
+    };
+    let dependencies = python::dependencies(py).unwrap_or_default();
+    let output = Output {
+        evaluation,
+        dependencies,
+    };
+    serde_json::to_writer(&mut reply, &output)?;
+    Ok(())
+}